@@ -0,0 +1,453 @@
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+
+declare_id!("CaW839Z6kSoo9hQPjweT9GmfsCGaf1M4jr3RJ6Y3GaA9");
+
+// Stand-in for the Jito restaking vault program, for local tests only. It mimics the
+// deposit (MintTo), withdrawal ticket (EnqueueWithdrawal) and burn (BurnWithdrawalTicket)
+// interface with single-byte instruction tags and the same account order, without
+// delegation, slashing or fees. The tags must match the ones in the adapter
+// (`stablefunds_program::jito_restaking`).
+#[program]
+pub mod mock_jito_vault {
+    use super::*;
+
+    #[instruction(discriminator = 0)]
+    pub fn initialize_config(ctx: Context<InitializeConfig>, epoch_length: u64) -> Result<()> {
+        require!(epoch_length > 0, ErrorCode::InvalidEpochLength);
+
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.epoch_length = epoch_length;
+        config.bump = ctx.bumps.config;
+
+        Ok(())
+    }
+
+    #[instruction(discriminator = 1)]
+    pub fn initialize_vault(ctx: Context<InitializeVault>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.supported_mint = ctx.accounts.supported_mint.key();
+        vault.vrt_mint = ctx.accounts.vrt_mint.key();
+        vault.tokens_deposited = 0;
+        vault.vrt_supply = 0;
+        vault.vrt_enqueued_for_cooldown = 0;
+        vault.bump = ctx.bumps.vault;
+        vault.vrt_mint_bump = ctx.bumps.vrt_mint;
+
+        Ok(())
+    }
+
+    #[instruction(discriminator = 11)]
+    pub fn mint_to(ctx: Context<MintToVrt>, amount_in: u64, min_amount_out: u64) -> Result<()> {
+        require!(amount_in > 0, ErrorCode::InvalidAmount);
+
+        let vault = &ctx.accounts.vault;
+        let amount_out = vault.vrt_for_tokens(amount_in)?;
+        require!(amount_out >= min_amount_out, ErrorCode::SlippageExceeded);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.depositor_token_account.to_account_info(),
+                    to: ctx.accounts.vault_token_account.to_account_info(),
+                    authority: ctx.accounts.depositor.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let supported_mint = vault.supported_mint;
+        let seeds = &[b"vault", supported_mint.as_ref(), &[vault.bump]];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.vrt_mint.to_account_info(),
+                    to: ctx.accounts.depositor_vrt_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[&seeds[..]],
+            ),
+            amount_out,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.tokens_deposited = vault.tokens_deposited.checked_add(amount_in).ok_or(ErrorCode::ArithmeticOverflow)?;
+        vault.vrt_supply = vault.vrt_supply.checked_add(amount_out).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    #[instruction(discriminator = 12)]
+    pub fn enqueue_withdrawal(ctx: Context<EnqueueWithdrawal>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.staker_vrt_token_account.to_account_info(),
+                    to: ctx.accounts.vault_staker_withdrawal_ticket_token_account.to_account_info(),
+                    authority: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        let ticket = &mut ctx.accounts.vault_staker_withdrawal_ticket;
+        ticket.vault = ctx.accounts.vault.key();
+        ticket.staker = ctx.accounts.staker.key();
+        ticket.base = ctx.accounts.base.key();
+        ticket.vrt_amount = amount;
+        ticket.slot_unstaked = Clock::get()?.slot;
+        ticket.bump = ctx.bumps.vault_staker_withdrawal_ticket;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.vrt_enqueued_for_cooldown = vault.vrt_enqueued_for_cooldown.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    #[instruction(discriminator = 14)]
+    pub fn burn_withdrawal_ticket(ctx: Context<BurnWithdrawalTicket>) -> Result<()> {
+        let ticket = &ctx.accounts.vault_staker_withdrawal_ticket;
+        let epoch_length = ctx.accounts.config.epoch_length;
+
+        // As in Jito, a ticket is withdrawable once a full epoch has passed after the one it was enqueued in
+        let current_slot = Clock::get()?.slot;
+        require!(
+            current_slot / epoch_length >= ticket.slot_unstaked / epoch_length + 2,
+            ErrorCode::WithdrawalTicketNotWithdrawable
+        );
+
+        let vrt_amount = ticket.vrt_amount;
+        let amount_out = ctx.accounts.vault.tokens_for_vrt(vrt_amount)?;
+
+        let vault_key = ctx.accounts.vault.key();
+        let base = ticket.base;
+        let ticket_seeds = &[
+            b"vault_staker_withdrawal_ticket",
+            vault_key.as_ref(),
+            base.as_ref(),
+            &[ticket.bump],
+        ];
+        token::burn(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.vrt_mint.to_account_info(),
+                    from: ctx.accounts.vault_staker_withdrawal_ticket_token_account.to_account_info(),
+                    authority: ctx.accounts.vault_staker_withdrawal_ticket.to_account_info(),
+                },
+                &[&ticket_seeds[..]],
+            ),
+            vrt_amount,
+        )?;
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::CloseAccount {
+                account: ctx.accounts.vault_staker_withdrawal_ticket_token_account.to_account_info(),
+                destination: ctx.accounts.staker.to_account_info(),
+                authority: ctx.accounts.vault_staker_withdrawal_ticket.to_account_info(),
+            },
+            &[&ticket_seeds[..]],
+        ))?;
+
+        let vault = &ctx.accounts.vault;
+        let supported_mint = vault.supported_mint;
+        let vault_seeds = &[b"vault", supported_mint.as_ref(), &[vault.bump]];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.vault_token_account.to_account_info(),
+                    to: ctx.accounts.staker_token_account.to_account_info(),
+                    authority: ctx.accounts.vault.to_account_info(),
+                },
+                &[&vault_seeds[..]],
+            ),
+            amount_out,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.tokens_deposited -= amount_out;
+        vault.vrt_supply -= vrt_amount;
+        vault.vrt_enqueued_for_cooldown -= vrt_amount;
+
+        Ok(())
+    }
+
+    // Picks up rewards transferred straight into the vault token account
+    #[instruction(discriminator = 21)]
+    pub fn update_vault_balance(ctx: Context<UpdateVaultBalance>) -> Result<()> {
+        let vault = &mut ctx.accounts.vault;
+        vault.tokens_deposited = ctx.accounts.vault_token_account.amount;
+
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub epoch_length: u64, // Slots per withdrawal cooldown epoch
+    pub bump: u8,
+}
+
+impl Config {
+    pub const SPACE: usize = 8 + 32 + 8 + 1;
+}
+
+#[account]
+pub struct Vault {
+    pub supported_mint: Pubkey,
+    pub vrt_mint: Pubkey,
+    pub tokens_deposited: u64,
+    pub vrt_supply: u64,
+    pub vrt_enqueued_for_cooldown: u64,
+    pub bump: u8,
+    pub vrt_mint_bump: u8,
+}
+
+impl Vault {
+    pub const SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 1;
+
+    pub fn vrt_for_tokens(&self, amount: u64) -> Result<u64> {
+        if self.vrt_supply == 0 || self.tokens_deposited == 0 {
+            return Ok(amount);
+        }
+        mul_div(amount, self.vrt_supply, self.tokens_deposited)
+    }
+
+    pub fn tokens_for_vrt(&self, vrt_amount: u64) -> Result<u64> {
+        if self.vrt_supply == 0 {
+            return Ok(0);
+        }
+        mul_div(vrt_amount, self.tokens_deposited, self.vrt_supply)
+    }
+}
+
+#[account]
+pub struct VaultStakerWithdrawalTicket {
+    pub vault: Pubkey,
+    pub staker: Pubkey,
+    pub base: Pubkey,
+    pub vrt_amount: u64,
+    pub slot_unstaked: u64,
+    pub bump: u8,
+}
+
+impl VaultStakerWithdrawalTicket {
+    pub const SPACE: usize = 8 + 32 + 32 + 32 + 8 + 8 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(init, payer = admin, space = Config::SPACE, seeds = [b"config"], bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeVault<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Vault::SPACE,
+        seeds = [b"vault", supported_mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"vrt-mint", vault.key().as_ref()],
+        bump,
+        mint::decimals = supported_mint.decimals,
+        mint::authority = vault
+    )]
+    pub vrt_mint: Account<'info, Mint>,
+
+    pub supported_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        associated_token::mint = supported_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct MintToVrt<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut, has_one = vrt_mint)]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub vrt_mint: Account<'info, Mint>,
+
+    pub depositor: Signer<'info>,
+
+    #[account(mut, token::mint = vault.supported_mint, token::authority = depositor)]
+    pub depositor_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = vault.supported_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = vrt_mint)]
+    pub depositor_vrt_token_account: Account<'info, TokenAccount>,
+
+    // Deposit fees are not charged by the stand-in
+    #[account(mut, token::mint = vrt_mint)]
+    pub vault_fee_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct EnqueueWithdrawal<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = staker,
+        space = VaultStakerWithdrawalTicket::SPACE,
+        seeds = [b"vault_staker_withdrawal_ticket", vault.key().as_ref(), base.key().as_ref()],
+        bump
+    )]
+    pub vault_staker_withdrawal_ticket: Account<'info, VaultStakerWithdrawalTicket>,
+
+    // Created by the caller beforehand, as with Jito
+    #[account(
+        mut,
+        associated_token::mint = vault.vrt_mint,
+        associated_token::authority = vault_staker_withdrawal_ticket
+    )]
+    pub vault_staker_withdrawal_ticket_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub staker: Signer<'info>,
+
+    #[account(mut, token::mint = vault.vrt_mint, token::authority = staker)]
+    pub staker_vrt_token_account: Account<'info, TokenAccount>,
+
+    pub base: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BurnWithdrawalTicket<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut, has_one = vrt_mint)]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        associated_token::mint = vault.supported_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub vrt_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub staker: Signer<'info>,
+
+    #[account(mut, token::mint = vault.supported_mint, token::authority = staker)]
+    pub staker_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = vault,
+        has_one = staker,
+        close = staker
+    )]
+    pub vault_staker_withdrawal_ticket: Account<'info, VaultStakerWithdrawalTicket>,
+
+    #[account(
+        mut,
+        associated_token::mint = vrt_mint,
+        associated_token::authority = vault_staker_withdrawal_ticket
+    )]
+    pub vault_staker_withdrawal_ticket_token_account: Account<'info, TokenAccount>,
+
+    // Withdrawal fees are not charged by the stand-in
+    #[account(mut, token::mint = vrt_mint)]
+    pub vault_fee_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = vrt_mint)]
+    pub program_fee_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateVaultBalance<'info> {
+    #[account(mut)]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        associated_token::mint = vault.supported_mint,
+        associated_token::authority = vault
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+}
+
+fn mul_div(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let result = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        / denominator as u128;
+
+    u64::try_from(result).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Epoch length must be positive")]
+    InvalidEpochLength,
+    #[msg("Amount must be positive")]
+    InvalidAmount,
+    #[msg("Fewer VRT than the minimum amount out")]
+    SlippageExceeded,
+    #[msg("Withdrawal ticket is still cooling down")]
+    WithdrawalTicketNotWithdrawable,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
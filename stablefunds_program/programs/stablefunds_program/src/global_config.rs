@@ -0,0 +1,109 @@
+use anchor_lang::prelude::*;
+
+pub const MAX_BPS: u16 = 10_000;
+
+// Protocol-wide limits that individual vaults and positions are configured within
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct GlobalConfigParams {
+    pub max_management_fee_bps: u16,  // Annualized management fee ceiling (e.g., 200 = 2%)
+    pub max_performance_fee_bps: u16, // Performance fee ceiling (e.g., 2000 = 20%)
+    pub max_withdrawal_delay: i64,    // Seconds after which a withdrawal ticket can be force-claimed
+    pub max_vault_tvl: u64,           // Ceiling on any vault's TVL cap, in underlying base units
+    pub min_deposit_floor: u64,       // Smallest minimum deposit a vault may configure
+    pub jito_vault_program: Pubkey,   // Jito restaking vault program strategies may deposit into
+}
+
+#[account]
+pub struct GlobalConfig {
+    pub authority: Pubkey,
+    pub max_management_fee_bps: u16,
+    pub max_performance_fee_bps: u16,
+    pub max_withdrawal_delay: i64,
+    pub max_vault_tvl: u64,
+    pub min_deposit_floor: u64,
+    pub jito_vault_program: Pubkey,
+    pub bump: u8,
+}
+
+impl GlobalConfig {
+    pub const SPACE: usize = 8 + 32 + 2 + 2 + 8 + 8 + 8 + 32 + 1;
+}
+
+// Only the program's upgrade authority can create the config and become its authority
+#[derive(Accounts)]
+pub struct InitializeGlobalConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ ErrorCode::Unauthorized)]
+    pub program: Program<'info, crate::program::StablefundsProgram>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key()) @ ErrorCode::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = GlobalConfig::SPACE,
+        seeds = [b"global-config"],
+        bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateGlobalConfig<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+// Implementation of functions
+
+pub fn initialize_global_config(ctx: Context<InitializeGlobalConfig>, params: GlobalConfigParams) -> Result<()> {
+    let global_config = &mut ctx.accounts.global_config;
+
+    global_config.authority = ctx.accounts.authority.key();
+    global_config.bump = ctx.bumps.global_config;
+    apply_params(global_config, params)
+}
+
+pub fn update_global_config(ctx: Context<UpdateGlobalConfig>, params: GlobalConfigParams) -> Result<()> {
+    apply_params(&mut ctx.accounts.global_config, params)
+}
+
+fn apply_params(global_config: &mut GlobalConfig, params: GlobalConfigParams) -> Result<()> {
+    require!(params.max_management_fee_bps <= MAX_BPS, ErrorCode::InvalidFeeLimit);
+    require!(params.max_performance_fee_bps <= MAX_BPS, ErrorCode::InvalidFeeLimit);
+    require!(params.max_withdrawal_delay >= 0, ErrorCode::InvalidWithdrawalDelay);
+    require!(params.min_deposit_floor > 0, ErrorCode::InvalidDepositLimit);
+
+    global_config.max_management_fee_bps = params.max_management_fee_bps;
+    global_config.max_performance_fee_bps = params.max_performance_fee_bps;
+    global_config.max_withdrawal_delay = params.max_withdrawal_delay;
+    global_config.max_vault_tvl = params.max_vault_tvl;
+    global_config.min_deposit_floor = params.min_deposit_floor;
+    global_config.jito_vault_program = params.jito_vault_program;
+
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Fee limit cannot exceed 100%")]
+    InvalidFeeLimit,
+    #[msg("Withdrawal delay cannot be negative")]
+    InvalidWithdrawalDelay,
+    #[msg("Minimum deposit floor must be positive")]
+    InvalidDepositLimit,
+    #[msg("Only the program's upgrade authority can initialize the global config")]
+    Unauthorized,
+}
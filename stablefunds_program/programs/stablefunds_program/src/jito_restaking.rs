@@ -0,0 +1,638 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+use crate::global_config::GlobalConfig;
+use crate::smart_vaults::{transfer_from_vault, SmartVault, StrategyAdapter};
+
+// Single-byte instruction tags of the Jito vault program
+pub const JITO_MINT_TO: u8 = 11;
+pub const JITO_ENQUEUE_WITHDRAWAL: u8 = 12;
+pub const JITO_BURN_WITHDRAWAL_TICKET: u8 = 14;
+// Lamports kept on the staker to pay rent for withdrawal tickets, refunded when they are burned
+pub const STAKER_RENT_RESERVE: u64 = 10_000_000;
+
+// Restaking position of a strategy adapter in a Jito vault. Jito needs a system-owned
+// staker to pay for withdrawal tickets, so a per-adapter staker PDA holds the VRT and
+// moves funds between the Jito vault and the adapter's position token account.
+#[account]
+pub struct JitoPosition {
+    pub smart_vault: Pubkey,
+    pub strategy_adapter: Pubkey,
+    pub jito_vault_program: Pubkey,
+    pub jito_vault: Pubkey,
+    pub vrt_mint: Pubkey,
+    pub staker_token_account: Pubkey,     // Supported token, passes deposits and withdrawals through
+    pub staker_vrt_token_account: Pubkey, // VRT received for deposits
+    pub staked_amount: u64,               // Cost of the VRT held or cooling down in a ticket
+    pub pending_ticket: Option<Pubkey>,   // At most one withdrawal ticket is cooling down at a time
+    pub ticket_vrt_amount: u64,
+    pub ticket_cost: u64,                 // Part of staked_amount the pending ticket redeems
+    pub bump: u8,
+    pub staker_bump: u8,
+    pub base_bump: u8,
+}
+
+impl JitoPosition {
+    pub const SPACE: usize = 8 + 32 * 7 + 8 + (1 + 32) + 8 + 8 + 1 + 1 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializeJitoPosition<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator
+    )]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    // Restaked principal is valued at cost in the vault's underlying, so the strategy must hold it
+    #[account(
+        has_one = smart_vault,
+        has_one = asset_mint,
+        constraint = strategy_adapter.holds_vault_underlying(&smart_vault) @ ErrorCode::UnsupportedStrategyAsset
+    )]
+    pub strategy_adapter: Box<Account<'info, StrategyAdapter>>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Box<Account<'info, GlobalConfig>>,
+
+    /// CHECK: Must be the Jito vault program allowed by the global config
+    #[account(
+        executable,
+        address = global_config.jito_vault_program
+    )]
+    pub jito_vault_program: UncheckedAccount<'info>,
+
+    /// CHECK: Owned by the Jito vault program, which validates it on every CPI
+    #[account(owner = jito_vault_program.key())]
+    pub jito_vault: UncheckedAccount<'info>,
+
+    // The strategy's underlying, deposited into the Jito vault
+    pub asset_mint: Box<Account<'info, Mint>>,
+
+    pub vrt_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        payer = curator,
+        space = JitoPosition::SPACE,
+        seeds = [b"jito-position", strategy_adapter.key().as_ref()],
+        bump
+    )]
+    pub jito_position: Box<Account<'info, JitoPosition>>,
+
+    /// CHECK: System-owned PDA that acts as the Jito staker
+    #[account(
+        seeds = [b"jito-staker", strategy_adapter.key().as_ref()],
+        bump
+    )]
+    pub staker: UncheckedAccount<'info>,
+
+    /// CHECK: PDA used as the base seed of withdrawal tickets
+    #[account(
+        seeds = [b"jito-ticket-base", jito_position.key().as_ref()],
+        bump
+    )]
+    pub ticket_base: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = curator,
+        seeds = [b"jito-staker-tokens", strategy_adapter.key().as_ref()],
+        bump,
+        token::mint = asset_mint,
+        token::authority = staker,
+    )]
+    pub staker_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = curator,
+        seeds = [b"jito-staker-vrt", strategy_adapter.key().as_ref()],
+        bump,
+        token::mint = vrt_mint,
+        token::authority = staker,
+    )]
+    pub staker_vrt_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct JitoRestake<'info> {
+    pub curator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        constraint = smart_vault.active
+    )]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    #[account(mut, has_one = smart_vault)]
+    pub strategy_adapter: Box<Account<'info, StrategyAdapter>>,
+
+    #[account(
+        mut,
+        constraint = position_token_account.key() == strategy_adapter.position_token_account
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"jito-position", strategy_adapter.key().as_ref()],
+        bump = jito_position.bump,
+        has_one = strategy_adapter,
+        has_one = jito_vault_program,
+        has_one = jito_vault,
+        has_one = vrt_mint,
+        has_one = staker_token_account,
+        has_one = staker_vrt_token_account
+    )]
+    pub jito_position: Box<Account<'info, JitoPosition>>,
+
+    /// CHECK: Staker PDA, verified by its seeds
+    #[account(
+        seeds = [b"jito-staker", strategy_adapter.key().as_ref()],
+        bump = jito_position.staker_bump
+    )]
+    pub staker: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub staker_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub staker_vrt_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Address pinned by the position
+    #[account(executable)]
+    pub jito_vault_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    pub jito_config: UncheckedAccount<'info>,
+
+    /// CHECK: Address pinned by the position
+    #[account(mut)]
+    pub jito_vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub vrt_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub jito_vault_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub jito_vault_fee_token_account: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct JitoRequestUnstake<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator
+    )]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    #[account(has_one = smart_vault)]
+    pub strategy_adapter: Box<Account<'info, StrategyAdapter>>,
+
+    #[account(
+        mut,
+        seeds = [b"jito-position", strategy_adapter.key().as_ref()],
+        bump = jito_position.bump,
+        has_one = strategy_adapter,
+        has_one = jito_vault_program,
+        has_one = jito_vault,
+        has_one = vrt_mint,
+        has_one = staker_vrt_token_account,
+        constraint = jito_position.pending_ticket.is_none() @ ErrorCode::TicketPending
+    )]
+    pub jito_position: Box<Account<'info, JitoPosition>>,
+
+    /// CHECK: Staker PDA, verified by its seeds
+    #[account(
+        mut,
+        seeds = [b"jito-staker", strategy_adapter.key().as_ref()],
+        bump = jito_position.staker_bump
+    )]
+    pub staker: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub staker_vrt_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Ticket base PDA, verified by its seeds
+    #[account(
+        seeds = [b"jito-ticket-base", jito_position.key().as_ref()],
+        bump = jito_position.base_bump
+    )]
+    pub ticket_base: UncheckedAccount<'info>,
+
+    /// CHECK: Address pinned by the position
+    #[account(executable)]
+    pub jito_vault_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    pub jito_config: UncheckedAccount<'info>,
+
+    /// CHECK: Address pinned by the position
+    #[account(mut)]
+    pub jito_vault: UncheckedAccount<'info>,
+
+    pub vrt_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Created by the Jito vault program, address verified by its seeds
+    #[account(
+        mut,
+        seeds = [b"vault_staker_withdrawal_ticket", jito_vault.key().as_ref(), ticket_base.key().as_ref()],
+        bump,
+        seeds::program = jito_vault_program.key()
+    )]
+    pub withdrawal_ticket: UncheckedAccount<'info>,
+
+    // Jito expects the ticket's VRT account to exist before the ticket is enqueued
+    #[account(
+        init,
+        payer = curator,
+        associated_token::mint = vrt_mint,
+        associated_token::authority = withdrawal_ticket
+    )]
+    pub withdrawal_ticket_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+// Permissionless: once the cooldown is over, anyone can bring the funds back to the vault
+#[derive(Accounts)]
+pub struct JitoBurnTicket<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump
+    )]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    #[account(mut, has_one = smart_vault)]
+    pub strategy_adapter: Box<Account<'info, StrategyAdapter>>,
+
+    #[account(
+        mut,
+        constraint = position_token_account.key() == strategy_adapter.position_token_account
+    )]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"jito-position", strategy_adapter.key().as_ref()],
+        bump = jito_position.bump,
+        has_one = strategy_adapter,
+        has_one = jito_vault_program,
+        has_one = jito_vault,
+        has_one = vrt_mint,
+        has_one = staker_token_account,
+        constraint = jito_position.pending_ticket == Some(withdrawal_ticket.key()) @ ErrorCode::NoPendingTicket
+    )]
+    pub jito_position: Box<Account<'info, JitoPosition>>,
+
+    /// CHECK: Staker PDA, verified by its seeds
+    #[account(
+        mut,
+        seeds = [b"jito-staker", strategy_adapter.key().as_ref()],
+        bump = jito_position.staker_bump
+    )]
+    pub staker: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub staker_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Address pinned by the position
+    #[account(executable)]
+    pub jito_vault_program: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    pub jito_config: UncheckedAccount<'info>,
+
+    /// CHECK: Address pinned by the position
+    #[account(mut)]
+    pub jito_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub jito_vault_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub vrt_mint: Box<Account<'info, Mint>>,
+
+    /// CHECK: Address pinned by the position
+    #[account(mut)]
+    pub withdrawal_ticket: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub withdrawal_ticket_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub jito_vault_fee_token_account: UncheckedAccount<'info>,
+
+    /// CHECK: Validated by the Jito vault program
+    #[account(mut)]
+    pub jito_program_fee_token_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Implementation of functions
+
+pub fn initialize_jito_position(ctx: Context<InitializeJitoPosition>) -> Result<()> {
+    let jito_position = &mut ctx.accounts.jito_position;
+
+    jito_position.smart_vault = ctx.accounts.smart_vault.key();
+    jito_position.strategy_adapter = ctx.accounts.strategy_adapter.key();
+    jito_position.jito_vault_program = ctx.accounts.jito_vault_program.key();
+    jito_position.jito_vault = ctx.accounts.jito_vault.key();
+    jito_position.vrt_mint = ctx.accounts.vrt_mint.key();
+    jito_position.staker_token_account = ctx.accounts.staker_token_account.key();
+    jito_position.staker_vrt_token_account = ctx.accounts.staker_vrt_token_account.key();
+    jito_position.staked_amount = 0;
+    jito_position.pending_ticket = None;
+    jito_position.ticket_vrt_amount = 0;
+    jito_position.ticket_cost = 0;
+    jito_position.bump = ctx.bumps.jito_position;
+    jito_position.staker_bump = ctx.bumps.staker;
+    jito_position.base_bump = ctx.bumps.ticket_base;
+
+    Ok(())
+}
+
+// Deposits deployed principal sitting in the position token account into the Jito vault
+pub fn jito_restake(ctx: Context<JitoRestake>, amount: u64, min_vrt_out: u64) -> Result<()> {
+    let strategy_adapter = &ctx.accounts.strategy_adapter;
+    require!(amount > 0, ErrorCode::InvalidAmount);
+    require!(
+        amount <= strategy_adapter.deployed_amount.saturating_sub(strategy_adapter.external_amount),
+        ErrorCode::InsufficientDeployedFunds
+    );
+
+    transfer_from_vault(
+        &ctx.accounts.smart_vault,
+        &ctx.accounts.position_token_account,
+        &ctx.accounts.staker_token_account,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    let staker = &ctx.accounts.staker;
+    let strategy_adapter_key = strategy_adapter.key();
+    let staker_seeds = &[
+        b"jito-staker",
+        strategy_adapter_key.as_ref(),
+        &[ctx.accounts.jito_position.staker_bump],
+    ];
+
+    invoke_jito(
+        &ctx.accounts.jito_vault_program,
+        JITO_MINT_TO,
+        &[amount, min_vrt_out],
+        &[
+            (ctx.accounts.jito_config.to_account_info(), false, false),
+            (ctx.accounts.jito_vault.to_account_info(), true, false),
+            (ctx.accounts.vrt_mint.to_account_info(), true, false),
+            (staker.to_account_info(), false, true),
+            (ctx.accounts.staker_token_account.to_account_info(), true, false),
+            (ctx.accounts.jito_vault_token_account.to_account_info(), true, false),
+            (ctx.accounts.staker_vrt_token_account.to_account_info(), true, false),
+            (ctx.accounts.jito_vault_fee_token_account.to_account_info(), true, false),
+            (ctx.accounts.token_program.to_account_info(), false, false),
+        ],
+        &[&staker_seeds[..]],
+    )?;
+
+    ctx.accounts.smart_vault.add_illiquid(amount)?;
+
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    strategy_adapter.external_amount = strategy_adapter.external_amount.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    let jito_position = &mut ctx.accounts.jito_position;
+    jito_position.staked_amount = jito_position.staked_amount.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    msg!("Restaked {} into Jito vault {}", amount, jito_position.jito_vault);
+    Ok(())
+}
+
+// Starts the Jito cooldown for `vrt_amount` by enqueueing a withdrawal ticket
+pub fn jito_request_unstake(ctx: Context<JitoRequestUnstake>, vrt_amount: u64) -> Result<()> {
+    let vrt_balance = ctx.accounts.staker_vrt_token_account.amount;
+    require!(vrt_amount > 0 && vrt_amount <= vrt_balance, ErrorCode::InvalidAmount);
+
+    // The staker pays the ticket rent and gets it back when the ticket is burned
+    let staker_lamports = ctx.accounts.staker.lamports();
+    if staker_lamports < STAKER_RENT_RESERVE {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.curator.to_account_info(),
+                    to: ctx.accounts.staker.to_account_info(),
+                },
+            ),
+            STAKER_RENT_RESERVE - staker_lamports,
+        )?;
+    }
+
+    let jito_position = &ctx.accounts.jito_position;
+    let ticket_cost = if vrt_amount == vrt_balance {
+        jito_position.staked_amount
+    } else {
+        let cost = (jito_position.staked_amount as u128)
+            .checked_mul(vrt_amount as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / vrt_balance as u128;
+        u64::try_from(cost).map_err(|_| ErrorCode::ArithmeticOverflow)?
+    };
+
+    let strategy_adapter_key = ctx.accounts.strategy_adapter.key();
+    let staker_seeds = &[
+        b"jito-staker",
+        strategy_adapter_key.as_ref(),
+        &[jito_position.staker_bump],
+    ];
+    let jito_position_key = jito_position.key();
+    let base_seeds = &[
+        b"jito-ticket-base",
+        jito_position_key.as_ref(),
+        &[jito_position.base_bump],
+    ];
+
+    invoke_jito(
+        &ctx.accounts.jito_vault_program,
+        JITO_ENQUEUE_WITHDRAWAL,
+        &[vrt_amount],
+        &[
+            (ctx.accounts.jito_config.to_account_info(), false, false),
+            (ctx.accounts.jito_vault.to_account_info(), true, false),
+            (ctx.accounts.withdrawal_ticket.to_account_info(), true, false),
+            (ctx.accounts.withdrawal_ticket_token_account.to_account_info(), true, false),
+            (ctx.accounts.staker.to_account_info(), true, true),
+            (ctx.accounts.staker_vrt_token_account.to_account_info(), true, false),
+            (ctx.accounts.ticket_base.to_account_info(), false, true),
+            (ctx.accounts.token_program.to_account_info(), false, false),
+            (ctx.accounts.system_program.to_account_info(), false, false),
+        ],
+        &[&staker_seeds[..], &base_seeds[..]],
+    )?;
+
+    let jito_position = &mut ctx.accounts.jito_position;
+    jito_position.pending_ticket = Some(ctx.accounts.withdrawal_ticket.key());
+    jito_position.ticket_vrt_amount = vrt_amount;
+    jito_position.ticket_cost = ticket_cost;
+
+    msg!("Enqueued Jito withdrawal of {} VRT", vrt_amount);
+    Ok(())
+}
+
+// Burns a cooled-down withdrawal ticket and returns the proceeds to the position token
+// account. Anything above the ticket's cost shows up as yield at the next harvest, and
+// anything below it, e.g. after a slashing, as a loss.
+pub fn jito_burn_ticket(ctx: Context<JitoBurnTicket>) -> Result<()> {
+    let jito_position = &ctx.accounts.jito_position;
+    let balance_before = ctx.accounts.staker_token_account.amount;
+
+    let strategy_adapter_key = ctx.accounts.strategy_adapter.key();
+    let staker_seeds = &[
+        b"jito-staker",
+        strategy_adapter_key.as_ref(),
+        &[jito_position.staker_bump],
+    ];
+
+    invoke_jito(
+        &ctx.accounts.jito_vault_program,
+        JITO_BURN_WITHDRAWAL_TICKET,
+        &[],
+        &[
+            (ctx.accounts.jito_config.to_account_info(), false, false),
+            (ctx.accounts.jito_vault.to_account_info(), true, false),
+            (ctx.accounts.jito_vault_token_account.to_account_info(), true, false),
+            (ctx.accounts.vrt_mint.to_account_info(), true, false),
+            (ctx.accounts.staker.to_account_info(), true, true),
+            (ctx.accounts.staker_token_account.to_account_info(), true, false),
+            (ctx.accounts.withdrawal_ticket.to_account_info(), true, false),
+            (ctx.accounts.withdrawal_ticket_token_account.to_account_info(), true, false),
+            (ctx.accounts.jito_vault_fee_token_account.to_account_info(), true, false),
+            (ctx.accounts.jito_program_fee_token_account.to_account_info(), true, false),
+            (ctx.accounts.token_program.to_account_info(), false, false),
+            (ctx.accounts.system_program.to_account_info(), false, false),
+        ],
+        &[&staker_seeds[..]],
+    )?;
+
+    ctx.accounts.staker_token_account.reload()?;
+    let returned = ctx.accounts.staker_token_account.amount.saturating_sub(balance_before);
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.staker_token_account.to_account_info(),
+                to: ctx.accounts.position_token_account.to_account_info(),
+                authority: ctx.accounts.staker.to_account_info(),
+            },
+            &[&staker_seeds[..]],
+        ),
+        returned,
+    )?;
+
+    let ticket_cost = jito_position.ticket_cost;
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    strategy_adapter.external_amount = strategy_adapter.external_amount.saturating_sub(ticket_cost);
+    ctx.accounts.smart_vault.remove_illiquid(ticket_cost);
+
+    let jito_position = &mut ctx.accounts.jito_position;
+    jito_position.staked_amount = jito_position.staked_amount.saturating_sub(ticket_cost);
+    jito_position.pending_ticket = None;
+    jito_position.ticket_vrt_amount = 0;
+    jito_position.ticket_cost = 0;
+
+    msg!("Jito withdrawal returned {} for a cost of {}", returned, ticket_cost);
+    Ok(())
+}
+
+// Helper functions
+
+// Jito vault instructions are a one-byte tag followed by little-endian u64 arguments.
+// Accounts are (account, is_writable, is_signer) in the order the instruction expects.
+fn invoke_jito<'info>(
+    jito_vault_program: &AccountInfo<'info>,
+    tag: u8,
+    args: &[u64],
+    accounts: &[(AccountInfo<'info>, bool, bool)],
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let mut data = Vec::with_capacity(1 + 8 * args.len());
+    data.push(tag);
+    for arg in args {
+        data.extend_from_slice(&arg.to_le_bytes());
+    }
+
+    let instruction = Instruction {
+        program_id: jito_vault_program.key(),
+        accounts: accounts
+            .iter()
+            .map(|(account, is_writable, is_signer)| {
+                if *is_writable {
+                    AccountMeta::new(account.key(), *is_signer)
+                } else {
+                    AccountMeta::new_readonly(account.key(), *is_signer)
+                }
+            })
+            .collect(),
+        data,
+    };
+
+    let account_infos = accounts.iter().map(|(account, _, _)| account.clone()).collect::<Vec<_>>();
+    invoke_signed(&instruction, &account_infos, signer_seeds)?;
+
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be positive and within the available balance")]
+    InvalidAmount,
+    #[msg("Not enough deployed principal in the position token account")]
+    InsufficientDeployedFunds,
+    #[msg("A Jito withdrawal ticket is already cooling down")]
+    TicketPending,
+    #[msg("Withdrawal ticket does not match the pending one")]
+    NoPendingTicket,
+    #[msg("Restaking strategies must hold the vault's underlying")]
+    UnsupportedStrategyAsset,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
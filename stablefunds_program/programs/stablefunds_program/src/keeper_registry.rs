@@ -0,0 +1,311 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+
+use crate::global_config::GlobalConfig;
+
+// Protections executed more often than this earn their keeper nothing, so a keeper cannot
+// farm rewards from a protection of its own that triggers on every call
+pub const MIN_REWARDED_COOLDOWN: i64 = 60 * 60;
+
+// Protocol fund that pays keepers for executing protection actions. Rewards are paid in
+// lamports out of this account's own balance, above its rent-exempt minimum.
+#[account]
+pub struct KeeperRegistry {
+    pub reward_per_execution: u64, // Lamports paid to a keeper per executed protection action
+    pub min_rewarded_value: u64,   // Oracle value an action must move to earn a reward, scaled by PRICE_SCALE
+    pub total_rewards_paid: u64,
+    pub bump: u8,
+}
+
+impl KeeperRegistry {
+    pub const SPACE: usize = 8 + 8 + 8 + 8 + 1;
+
+    // Reward for one execution that moved `value_moved` for a protection with `cooldown_period`,
+    // out of `available` lamports above the registry's rent-exempt minimum
+    pub fn reward_for(&self, value_moved: u64, cooldown_period: i64, available: u64) -> u64 {
+        let rewarded = value_moved > 0
+            && value_moved >= self.min_rewarded_value
+            && cooldown_period >= MIN_REWARDED_COOLDOWN;
+        if rewarded && available >= self.reward_per_execution {
+            self.reward_per_execution
+        } else {
+            0
+        }
+    }
+}
+
+// A keeper allowlisted by the protocol authority
+#[account]
+pub struct Keeper {
+    pub authority: Pubkey,      // Signer the keeper executes protection with
+    pub active: bool,
+    pub total_executions: u64,
+    pub total_rewards: u64,
+    pub registered_at: i64,
+    pub bump: u8,
+}
+
+impl Keeper {
+    pub const SPACE: usize = 8 + 32 + 1 + 8 + 8 + 8 + 1;
+}
+
+#[derive(Accounts)]
+pub struct InitializeKeeperRegistry<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = KeeperRegistry::SPACE,
+        seeds = [b"keeper-registry"],
+        bump
+    )]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetKeeperReward<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper-registry"],
+        bump = keeper_registry.bump
+    )]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
+}
+
+// Anyone can top up the reward fund
+#[derive(Accounts)]
+pub struct FundKeeperRegistry<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper-registry"],
+        bump = keeper_registry.bump
+    )]
+    pub keeper_registry: Account<'info, KeeperRegistry>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterKeeper<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: The keeper's signing key, only used as a seed
+    pub keeper_authority: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = Keeper::SPACE,
+        seeds = [b"keeper", keeper_authority.key().as_ref()],
+        bump
+    )]
+    pub keeper: Account<'info, Keeper>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetKeeperActive<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper", keeper.authority.as_ref()],
+        bump = keeper.bump
+    )]
+    pub keeper: Account<'info, Keeper>,
+}
+
+// Implementation of functions
+
+pub fn initialize_keeper_registry(
+    ctx: Context<InitializeKeeperRegistry>,
+    reward_per_execution: u64,
+    min_rewarded_value: u64,
+) -> Result<()> {
+    let keeper_registry = &mut ctx.accounts.keeper_registry;
+
+    keeper_registry.reward_per_execution = reward_per_execution;
+    keeper_registry.min_rewarded_value = min_rewarded_value;
+    keeper_registry.total_rewards_paid = 0;
+    keeper_registry.bump = ctx.bumps.keeper_registry;
+
+    Ok(())
+}
+
+pub fn set_keeper_reward(ctx: Context<SetKeeperReward>, reward_per_execution: u64, min_rewarded_value: u64) -> Result<()> {
+    let keeper_registry = &mut ctx.accounts.keeper_registry;
+
+    keeper_registry.reward_per_execution = reward_per_execution;
+    keeper_registry.min_rewarded_value = min_rewarded_value;
+
+    Ok(())
+}
+
+pub fn fund_keeper_registry(ctx: Context<FundKeeperRegistry>, amount: u64) -> Result<()> {
+    require!(amount > 0, ErrorCode::InvalidAmount);
+
+    system_program::transfer(
+        CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.funder.to_account_info(),
+                to: ctx.accounts.keeper_registry.to_account_info(),
+            },
+        ),
+        amount,
+    )
+}
+
+pub fn register_keeper(ctx: Context<RegisterKeeper>) -> Result<()> {
+    let keeper = &mut ctx.accounts.keeper;
+
+    keeper.authority = ctx.accounts.keeper_authority.key();
+    keeper.active = true;
+    keeper.total_executions = 0;
+    keeper.total_rewards = 0;
+    keeper.registered_at = Clock::get()?.unix_timestamp;
+    keeper.bump = ctx.bumps.keeper;
+
+    Ok(())
+}
+
+pub fn set_keeper_active(ctx: Context<SetKeeperActive>, active: bool) -> Result<()> {
+    ctx.accounts.keeper.active = active;
+
+    Ok(())
+}
+
+// Helper functions
+
+// Pays the keeper's reward for one execution that moved `value_moved` for a protection with
+// `cooldown_period`. Executions below the registry's minimum value or the minimum cooldown,
+// and executions against an underfunded registry, are paid nothing rather than failing, so
+// protection never depends on the fund's balance. Returns the amount paid.
+pub(crate) fn reward_keeper<'info>(
+    keeper_registry: &mut Account<'info, KeeperRegistry>,
+    keeper: &mut Account<'info, Keeper>,
+    recipient: &AccountInfo<'info>,
+    value_moved: u64,
+    cooldown_period: i64,
+) -> Result<u64> {
+    let registry_info = keeper_registry.to_account_info();
+    let rent_exempt = Rent::get()?.minimum_balance(registry_info.data_len());
+    let available = registry_info.lamports().saturating_sub(rent_exempt);
+    let reward = keeper_registry.reward_for(value_moved, cooldown_period, available);
+
+    if reward > 0 {
+        **registry_info.try_borrow_mut_lamports()? -= reward;
+        **recipient.try_borrow_mut_lamports()? = recipient.lamports().checked_add(reward).ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    keeper_registry.total_rewards_paid = keeper_registry.total_rewards_paid.checked_add(reward).ok_or(ErrorCode::ArithmeticOverflow)?;
+    keeper.total_executions = keeper.total_executions.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+    keeper.total_rewards = keeper.total_rewards.checked_add(reward).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    Ok(reward)
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Amount must be positive")]
+    InvalidAmount,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REWARD: u64 = 5_000;
+
+    // Rewards actions moving at least 100 of value
+    fn keeper_registry() -> KeeperRegistry {
+        KeeperRegistry {
+            reward_per_execution: REWARD,
+            min_rewarded_value: 100,
+            total_rewards_paid: 0,
+            bump: 255,
+        }
+    }
+
+    #[test]
+    fn actions_above_the_minimum_value_and_cooldown_are_rewarded() {
+        let registry = keeper_registry();
+
+        assert_eq!(registry.reward_for(100, MIN_REWARDED_COOLDOWN, REWARD), REWARD);
+        assert_eq!(registry.reward_for(u64::MAX, i64::MAX, u64::MAX), REWARD);
+    }
+
+    #[test]
+    fn actions_below_the_minimum_value_earn_nothing() {
+        let registry = keeper_registry();
+
+        assert_eq!(registry.reward_for(99, MIN_REWARDED_COOLDOWN, REWARD), 0);
+        assert_eq!(registry.reward_for(0, MIN_REWARDED_COOLDOWN, REWARD), 0);
+
+        // Without a minimum value, actions must still move something
+        let registry = KeeperRegistry {
+            min_rewarded_value: 0,
+            ..keeper_registry()
+        };
+        assert_eq!(registry.reward_for(1, MIN_REWARDED_COOLDOWN, REWARD), REWARD);
+        assert_eq!(registry.reward_for(0, MIN_REWARDED_COOLDOWN, REWARD), 0);
+    }
+
+    #[test]
+    fn protections_with_short_cooldowns_earn_nothing() {
+        let registry = keeper_registry();
+
+        assert_eq!(registry.reward_for(100, MIN_REWARDED_COOLDOWN - 1, REWARD), 0);
+        assert_eq!(registry.reward_for(100, 0, REWARD), 0);
+        assert_eq!(registry.reward_for(100, -1, REWARD), 0);
+    }
+
+    #[test]
+    fn an_underfunded_registry_pays_nothing() {
+        let registry = keeper_registry();
+
+        assert_eq!(registry.reward_for(100, MIN_REWARDED_COOLDOWN, REWARD - 1), 0);
+        assert_eq!(registry.reward_for(100, MIN_REWARDED_COOLDOWN, 0), 0);
+    }
+}
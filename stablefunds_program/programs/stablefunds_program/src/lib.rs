@@ -7,13 +7,39 @@ use std::mem::size_of;
 declare_id!("8eHztpVrS6k1k7GeuHV2xgjioHiUKCwmV4P3EbNTLeWo");
 
 // Other modules are included here
+pub mod global_config;
+pub mod oracle;
 pub mod smart_vaults;
+pub mod withdrawal_queue;
+pub mod vault_lifecycle;
 pub mod liquidation_protection;
+pub mod yield_history;
+pub mod jito_restaking;
+pub mod swap_adapter;
+pub mod multi_asset;
+pub mod keeper_registry;
+pub mod protection_migration;
+pub mod protection_batch;
+
+use global_config::*;
+use oracle::*;
+use smart_vaults::*;
+use withdrawal_queue::*;
+use vault_lifecycle::*;
+use liquidation_protection::*;
+use yield_history::*;
+use jito_restaking::*;
+use swap_adapter::*;
+use multi_asset::*;
+use keeper_registry::*;
+use protection_migration::*;
+use protection_batch::*;
 
 #[program]
 pub mod stablefunds_program {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_stablecoin(
         ctx: Context<CreateStablecoin>,
         name: String,
@@ -68,6 +94,47 @@ pub mod stablefunds_program {
             user_collateral.stablecoin_config = stablecoin_config.key();
         }
 
+        // Smart vault shares are taken into custody by the stablecoin vault
+        if let CollateralType::SmartVaultShares { smart_vault } = stablecoin_config.collateral_type {
+            let (
+                Some(vault),
+                Some(vault_share_mint),
+                Some(user_share_account),
+                Some(vault_share_token_account),
+                Some(underlying_price_feed),
+            ) = (
+                &ctx.accounts.smart_vault,
+                &ctx.accounts.vault_share_mint,
+                &ctx.accounts.user_share_account,
+                &ctx.accounts.vault_share_token_account,
+                &ctx.accounts.underlying_price_feed,
+            ) else {
+                return Err(ErrorCode::MissingCollateralAccounts.into());
+            };
+
+            require!(vault.key() == smart_vault, ErrorCode::InvalidCollateralAccount);
+            require!(vault_share_mint.key() == vault.share_mint, ErrorCode::InvalidCollateralAccount);
+            require!(user_share_account.mint == vault.share_mint, ErrorCode::InvalidCollateralAccount);
+            require!(vault_share_token_account.mint == vault.share_mint, ErrorCode::InvalidCollateralAccount);
+            require!(
+                vault_share_token_account.owner == ctx.accounts.stablecoin_vault.key(),
+                ErrorCode::InvalidCollateralAccount
+            );
+
+            let value = vault.share_collateral_value(amount, vault_share_mint, underlying_price_feed)?;
+            require!(value > 0, ErrorCode::InvalidCollateralAccount);
+
+            let cpi_accounts = token::Transfer {
+                from: user_share_account.to_account_info(),
+                to: vault_share_token_account.to_account_info(),
+                authority: user.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            token::transfer(CpiContext::new(cpi_program, cpi_accounts), amount)?;
+
+            msg!("Smart vault shares deposited: {}, collateral value: {}", amount, value);
+        }
+
         // Update user collateral amount
         user_collateral.amount = user_collateral.amount.checked_add(amount)
             .ok_or(ErrorCode::ArithmeticOverflow)?;
@@ -90,6 +157,28 @@ pub mod stablefunds_program {
             user_stablecoin.stablecoin_config = stablecoin_config.key();
         }
 
+        // Borrowing against smart vault shares is bounded by their risk-adjusted value
+        if let CollateralType::SmartVaultShares { .. } = stablecoin_config.collateral_type {
+            let (Some(user_collateral), Some(vault), Some(vault_share_mint), Some(underlying_price_feed)) = (
+                &ctx.accounts.user_collateral,
+                &ctx.accounts.smart_vault,
+                &ctx.accounts.vault_share_mint,
+                &ctx.accounts.underlying_price_feed,
+            ) else {
+                return Err(ErrorCode::MissingCollateralAccounts.into());
+            };
+
+            let collateral_price = CollateralPrice::load(stablecoin_config, vault_share_mint, underlying_price_feed, Some(vault))?;
+            let position = PositionHealth {
+                collateral_value: collateral_price.value_of(user_collateral.amount)?,
+                debt: user_stablecoin.amount.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?,
+            };
+            require!(
+                position.is_collateralized(stablecoin_config.collateralization_ratio),
+                ErrorCode::InsufficientCollateral
+            );
+        }
+
         // Mint the stablecoins to the user
         let cpi_accounts = token::MintTo {
             mint: ctx.accounts.stablecoin_mint.to_account_info(),
@@ -109,6 +198,302 @@ pub mod stablefunds_program {
         msg!("Stablecoin minted: {}", amount);
         Ok(())
     }
+
+    // Oracle instructions
+
+    pub fn initialize_price_feed(
+        ctx: Context<InitializePriceFeed>,
+        price_authority: Pubkey,
+    ) -> Result<()> {
+        oracle::initialize_price_feed(ctx, price_authority)
+    }
+
+    pub fn update_price_feed(ctx: Context<UpdatePriceFeed>, price: u64) -> Result<()> {
+        oracle::update_price_feed(ctx, price)
+    }
+
+    // Global config instructions
+
+    pub fn initialize_global_config(
+        ctx: Context<InitializeGlobalConfig>,
+        params: GlobalConfigParams,
+    ) -> Result<()> {
+        global_config::initialize_global_config(ctx, params)
+    }
+
+    pub fn update_global_config(
+        ctx: Context<UpdateGlobalConfig>,
+        params: GlobalConfigParams,
+    ) -> Result<()> {
+        global_config::update_global_config(ctx, params)
+    }
+
+    // Smart vault instructions
+
+    pub fn initialize_smart_vault(
+        ctx: Context<InitializeSmartVault>,
+        vault_params: SmartVaultParams,
+    ) -> Result<()> {
+        smart_vaults::initialize_smart_vault(ctx, vault_params)
+    }
+
+    pub fn deposit_to_strategy(
+        ctx: Context<DepositToStrategy>,
+        amount: u64,
+    ) -> Result<()> {
+        smart_vaults::deposit_to_strategy(ctx, amount)
+    }
+
+    pub fn withdraw_from_strategy(
+        ctx: Context<WithdrawFromStrategy>,
+        amount: u64,
+    ) -> Result<()> {
+        smart_vaults::withdraw_from_strategy(ctx, amount)
+    }
+
+    pub fn update_strategy_allocation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateStrategyAllocation<'info>>,
+        allocations: Vec<StrategyAllocation>,
+    ) -> Result<()> {
+        smart_vaults::update_strategy_allocation(ctx, allocations)
+    }
+
+    pub fn register_strategy_adapter(
+        ctx: Context<RegisterStrategyAdapter>,
+        strategy_id: u8,
+        risk_tier: u8,
+    ) -> Result<()> {
+        smart_vaults::register_strategy_adapter(ctx, strategy_id, risk_tier)
+    }
+
+    pub fn set_deposit_limits(
+        ctx: Context<SetDepositLimits>,
+        tvl_cap: u64,
+        user_cap: u64,
+        min_deposit: u64,
+    ) -> Result<()> {
+        smart_vaults::set_deposit_limits(ctx, tvl_cap, user_cap, min_deposit)
+    }
+
+    pub fn set_vault_roles(
+        ctx: Context<SetVaultRoles>,
+        curator: Pubkey,
+        guardian: Pubkey,
+    ) -> Result<()> {
+        smart_vaults::set_vault_roles(ctx, curator, guardian)
+    }
+
+    pub fn deallocate_strategy(ctx: Context<DeallocateStrategy>) -> Result<()> {
+        smart_vaults::deallocate_strategy(ctx)
+    }
+
+    pub fn get_vault_risk(ctx: Context<GetVaultRisk>) -> Result<VaultRiskReport> {
+        smart_vaults::get_vault_risk(ctx)
+    }
+
+    pub fn register_swap_adapter(ctx: Context<RegisterSwapAdapter>) -> Result<()> {
+        swap_adapter::register_swap_adapter(ctx)
+    }
+
+    pub fn swap_into_strategy<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapStrategy<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        multi_asset::swap_into_strategy(ctx, amount_in, min_amount_out)
+    }
+
+    pub fn swap_out_of_strategy<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapStrategy<'info>>,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<()> {
+        multi_asset::swap_out_of_strategy(ctx, amount_in, min_amount_out)
+    }
+
+    pub fn get_vault_value<'info>(
+        ctx: Context<'_, '_, 'info, 'info, GetVaultValue<'info>>,
+    ) -> Result<VaultValuation> {
+        multi_asset::get_vault_value(ctx)
+    }
+
+    pub fn initialize_jito_position(ctx: Context<InitializeJitoPosition>) -> Result<()> {
+        jito_restaking::initialize_jito_position(ctx)
+    }
+
+    pub fn jito_restake(ctx: Context<JitoRestake>, amount: u64, min_vrt_out: u64) -> Result<()> {
+        jito_restaking::jito_restake(ctx, amount, min_vrt_out)
+    }
+
+    pub fn jito_request_unstake(ctx: Context<JitoRequestUnstake>, vrt_amount: u64) -> Result<()> {
+        jito_restaking::jito_request_unstake(ctx, vrt_amount)
+    }
+
+    pub fn jito_burn_ticket(ctx: Context<JitoBurnTicket>) -> Result<()> {
+        jito_restaking::jito_burn_ticket(ctx)
+    }
+
+    pub fn initialize_yield_history(ctx: Context<InitializeYieldHistory>) -> Result<()> {
+        yield_history::initialize_yield_history(ctx)
+    }
+
+    pub fn get_vault_apy(ctx: Context<GetVaultApy>) -> Result<VaultApyReport> {
+        yield_history::get_vault_apy(ctx)
+    }
+
+    pub fn rebalance_strategies<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RebalanceStrategies<'info>>,
+    ) -> Result<()> {
+        smart_vaults::rebalance_strategies(ctx)
+    }
+
+    pub fn harvest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Harvest<'info>>,
+    ) -> Result<()> {
+        smart_vaults::harvest(ctx)
+    }
+
+    pub fn claim_yield(ctx: Context<ClaimYield>) -> Result<()> {
+        smart_vaults::claim_yield(ctx)
+    }
+
+    pub fn set_vault_fees(
+        ctx: Context<SetVaultFees>,
+        management_fee_bps: u16,
+        performance_fee_bps: u16,
+        fee_recipient: Pubkey,
+    ) -> Result<()> {
+        smart_vaults::set_vault_fees(ctx, management_fee_bps, performance_fee_bps, fee_recipient)
+    }
+
+    pub fn request_withdrawal(
+        ctx: Context<RequestWithdrawal>,
+        amount: u64,
+    ) -> Result<()> {
+        withdrawal_queue::request_withdrawal(ctx, amount)
+    }
+
+    pub fn fulfill_withdrawal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FulfillWithdrawal<'info>>,
+    ) -> Result<()> {
+        withdrawal_queue::fulfill_withdrawal(ctx)
+    }
+
+    pub fn claim_withdrawal<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClaimWithdrawal<'info>>,
+    ) -> Result<()> {
+        withdrawal_queue::claim_withdrawal(ctx)
+    }
+
+    pub fn pause_vault(ctx: Context<PauseVault>) -> Result<()> {
+        vault_lifecycle::pause_vault(ctx)
+    }
+
+    pub fn resume_vault(ctx: Context<ResumeVault>) -> Result<()> {
+        vault_lifecycle::resume_vault(ctx)
+    }
+
+    pub fn emergency_withdraw_all<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EmergencyWithdrawAll<'info>>,
+    ) -> Result<()> {
+        vault_lifecycle::emergency_withdraw_all(ctx)
+    }
+
+    pub fn close_strategy_adapter(ctx: Context<CloseStrategyAdapter>) -> Result<()> {
+        vault_lifecycle::close_strategy_adapter(ctx)
+    }
+
+    pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+        vault_lifecycle::close_vault(ctx)
+    }
+
+    // Liquidation protection instructions
+
+    pub fn configure_protection(
+        ctx: Context<ConfigureProtection>,
+        config: ProtectionConfig,
+    ) -> Result<()> {
+        liquidation_protection::configure_protection(ctx, config)
+    }
+
+    pub fn activate_protection(ctx: Context<ActivateProtection>) -> Result<()> {
+        liquidation_protection::activate_protection(ctx)
+    }
+
+    pub fn deactivate_protection(ctx: Context<DeactivateProtection>) -> Result<()> {
+        liquidation_protection::deactivate_protection(ctx)
+    }
+
+    pub fn execute_protection_action<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteProtectionAction<'info>>,
+        action_type: ProtectionActionType,
+    ) -> Result<()> {
+        liquidation_protection::execute_protection_action(ctx, action_type)
+    }
+
+    pub fn close_protection_action_record(ctx: Context<CloseProtectionActionRecord>) -> Result<()> {
+        liquidation_protection::close_protection_action_record(ctx)
+    }
+
+    pub fn execute_protection_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteProtectionBatch<'info>>,
+        action_type: ProtectionActionType,
+    ) -> Result<Vec<BatchProtectionResult>> {
+        protection_batch::execute_protection_batch(ctx, action_type)
+    }
+
+    pub fn close_protection<'info>(ctx: Context<'_, '_, 'info, 'info, CloseProtection<'info>>) -> Result<()> {
+        liquidation_protection::close_protection(ctx)
+    }
+
+    pub fn simulate_protection(
+        ctx: Context<SimulateProtection>,
+        action_type: ProtectionActionType,
+    ) -> Result<ProtectionSimulation> {
+        liquidation_protection::simulate_protection(ctx, action_type)
+    }
+
+    pub fn migrate_protection_account(
+        ctx: Context<MigrateProtectionAccount>,
+        budget_amount: u64,
+        budget_window: i64,
+    ) -> Result<()> {
+        protection_migration::migrate_protection_account(ctx, budget_amount, budget_window)
+    }
+
+    pub fn migrate_protection_action_record(ctx: Context<MigrateProtectionActionRecord>) -> Result<()> {
+        protection_migration::migrate_protection_action_record(ctx)
+    }
+
+    // Keeper registry instructions
+
+    pub fn initialize_keeper_registry(
+        ctx: Context<InitializeKeeperRegistry>,
+        reward_per_execution: u64,
+        min_rewarded_value: u64,
+    ) -> Result<()> {
+        keeper_registry::initialize_keeper_registry(ctx, reward_per_execution, min_rewarded_value)
+    }
+
+    pub fn set_keeper_reward(
+        ctx: Context<SetKeeperReward>,
+        reward_per_execution: u64,
+        min_rewarded_value: u64,
+    ) -> Result<()> {
+        keeper_registry::set_keeper_reward(ctx, reward_per_execution, min_rewarded_value)
+    }
+
+    pub fn fund_keeper_registry(ctx: Context<FundKeeperRegistry>, amount: u64) -> Result<()> {
+        keeper_registry::fund_keeper_registry(ctx, amount)
+    }
+
+    pub fn register_keeper(ctx: Context<RegisterKeeper>) -> Result<()> {
+        keeper_registry::register_keeper(ctx)
+    }
+
+    pub fn set_keeper_active(ctx: Context<SetKeeperActive>, active: bool) -> Result<()> {
+        keeper_registry::set_keeper_active(ctx, active)
+    }
 }
 
 #[derive(Accounts)]
@@ -213,6 +598,15 @@ pub struct DepositCollateral<'info> {
     pub user_stablebond_token_account: Option<Box<Account<'info, TokenAccount>>>,
     pub vault_stablebond_token_account: Option<Box<Account<'info, TokenAccount>>>,
 
+    /// Optional accounts for smart vault share collateral
+    pub smart_vault: Option<Box<Account<'info, SmartVault>>>,
+    pub vault_share_mint: Option<Box<Account<'info, Mint>>>,
+    #[account(mut)]
+    pub user_share_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub vault_share_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    pub underlying_price_feed: Option<Box<Account<'info, PriceFeed>>>,
+
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -262,6 +656,20 @@ pub struct MintStablecoin<'info> {
     )]
     pub user_stablecoin: Account<'info, UserStablecoin>,
 
+    /// Optional accounts for smart vault share collateral, which is valued at mint
+    #[account(
+        seeds = [
+            b"user-collateral",
+            user.key().as_ref(),
+            stablecoin_config.key().as_ref()
+        ],
+        bump
+    )]
+    pub user_collateral: Option<Box<Account<'info, UserCollateral>>>,
+    pub smart_vault: Option<Box<Account<'info, SmartVault>>>,
+    pub vault_share_mint: Option<Box<Account<'info, Mint>>>,
+    pub underlying_price_feed: Option<Box<Account<'info, PriceFeed>>>,
+
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
@@ -290,7 +698,7 @@ impl StablecoinConfig {
         4 + symbol.len() + // symbol
         4 + description.len() + // description
         1 + // icon_index
-        1 + // collateral_type
+        1 + 32 + // collateral_type (largest variant)
         8 + // collateralization_ratio
         32 + // mint
         8 + // total_supply
@@ -317,10 +725,17 @@ pub enum CollateralType {
     Sol,
     Stablebond,
     Usdc,
+    SmartVaultShares { smart_vault: Pubkey },
 }
 
 #[error_code]
 pub enum ErrorCode {
     #[msg("Arithmetic overflow")]
     ArithmeticOverflow,
+    #[msg("Required collateral accounts are missing")]
+    MissingCollateralAccounts,
+    #[msg("Collateral account does not match the stablecoin's collateral")]
+    InvalidCollateralAccount,
+    #[msg("Collateral value does not cover the debt at the collateralization ratio")]
+    InsufficientCollateral,
 }
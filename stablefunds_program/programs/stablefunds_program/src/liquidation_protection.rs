@@ -1,69 +1,329 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::program_option::COption;
+use anchor_spl::token::{self, spl_token, Approve, Burn, Revoke, Token, TokenAccount, Transfer};
+
+use crate::global_config::MAX_BPS;
+use crate::keeper_registry::{reward_keeper, Keeper, KeeperRegistry};
+use crate::oracle::PriceFeed;
+use crate::smart_vaults::{mul_div, transfer_from_vault, SmartVault};
+use crate::swap_adapter::{swap_exact_in, SwapAdapter};
+use crate::{CollateralType, StablecoinConfig, UserCollateral, UserStablecoin};
+
+// Health ratios are in basis points (e.g., 15_000 = 150%)
+pub const HEALTH_RATIO_SCALE: u64 = 10_000;
+
+// Deleveraging must sell collateral within this distance of its oracle value
+pub const DELEVERAGE_MAX_SLIPPAGE_BPS: u16 = 100;
+
+// Layout version of protection accounts and action records, bumped with each migration
+pub const PROTECTION_LAYOUT_VERSION: u8 = 1;
+
+// How a protection action restores a position's health
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtectionActionType {
+    AutoRepay,        // Burn the owner's stablecoins to repay debt
+    AddCollateral,    // Top up collateral from the auto collateral source
+    UnwindSmartVault, // Redeem the owner's smart vault shares into collateral
+    Deleverage,       // Sell part of the collateral to repay debt
+}
+
+// Which action types a protection may take
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtectionMode {
+    AutoRepay,
+    AddCollateral,
+    Both, // Auto-repay or add collateral
+    UnwindSmartVault,
+    Deleverage,
+    All,
+}
+
+impl ProtectionMode {
+    pub fn allows(&self, action_type: ProtectionActionType) -> bool {
+        match self {
+            ProtectionMode::AutoRepay => action_type == ProtectionActionType::AutoRepay,
+            ProtectionMode::AddCollateral => action_type == ProtectionActionType::AddCollateral,
+            ProtectionMode::Both => matches!(
+                action_type,
+                ProtectionActionType::AutoRepay | ProtectionActionType::AddCollateral
+            ),
+            ProtectionMode::UnwindSmartVault => {
+                action_type == ProtectionActionType::UnwindSmartVault
+            }
+            ProtectionMode::Deleverage => action_type == ProtectionActionType::Deleverage,
+            ProtectionMode::All => true,
+        }
+    }
+}
 
 // Protection configuration parameters
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct ProtectionConfig {
-    pub threshold_percentage: u8,       // Health ratio threshold % (e.g., 120 = 120%)
-    pub protection_mode: u8,            // 1 = Auto-repay, 2 = Add collateral, 3 = Both
-    pub max_protection_amount: u64,     // Maximum amount for auto-protection
-    pub notification_only: bool,        // True if notification only (no auto-protection)
-    pub cooldown_period: i64,           // Time between protection actions in seconds
+    pub trigger_ratio_bps: u16, // Protection triggers below this health ratio (e.g., 12_000 = 120%)
+    pub target_ratio_bps: u16,  // Health ratio protection restores the position to
+    pub protection_mode: ProtectionMode, // Action types protection may take
+    pub max_protection_amount: u64, // Maximum amount for auto-protection
+    pub notification_only: bool, // True if notification only (no auto-protection)
+    pub cooldown_period: i64,   // Time between protection actions in seconds
     pub auto_collateral_source: Pubkey, // Token account to source auto-collateral from
+    pub budget_amount: u64, // Maximum oracle value protection may move per budget window, scaled by PRICE_SCALE
+    pub budget_window: i64, // Length of the budget window in seconds
 }
 
 // Represents a configured protection for a collateral position
 #[account]
 pub struct ProtectionAccount {
-    pub owner: Pubkey,                   // Owner of the protected position
-    pub stablecoin_mint: Pubkey,         // The stablecoin being protected
-    pub collateral_mint: Pubkey,         // The collateral being protected
-    pub config: ProtectionConfig,        // Protection configuration
-    pub is_active: bool,                 // Whether protection is active
-    pub last_protection_time: i64,       // Last time protection was triggered
-    pub total_protection_actions: u64,   // Total number of protection actions
-    pub total_protected_amount: u64,     // Total amount protected
-    pub last_health_ratio: u16,          // Last recorded health ratio (scaled by 100)
-    pub bump: u8,                        // PDA bump
-}
-
-// Protection action history record 
+    pub owner: Pubkey,                 // Owner of the protected position
+    pub stablecoin_mint: Pubkey,       // The stablecoin being protected
+    pub collateral_mint: Pubkey,       // The collateral being protected
+    pub config: ProtectionConfig,      // Protection configuration
+    pub is_active: bool,               // Whether protection is active
+    pub last_protection_time: i64,     // Last time protection was triggered
+    pub total_protection_actions: u64, // Total number of protection actions
+    pub total_protected_amount: u64,   // Total amount protected
+    pub last_health_ratio: u16,        // Last recorded health ratio in basis points
+    pub next_action_id: u64,           // Seed of the next action record
+    pub open_action_records: u64, // Action records not yet closed, the account only closes without any
+    pub budget_window_start: i64, // Start of the current budget window
+    pub budget_spent: u64,        // Oracle value moved by protection in the current budget window
+    pub custodied_collateral: u64, // Collateral protection moved into the stablecoin vault, the most deleveraging may sell
+    pub bump: u8,                  // PDA bump
+    pub version: u8,               // Layout version, see `PROTECTION_LAYOUT_VERSION`
+}
+
+impl ProtectionAccount {
+    // Budget value left at `now`, counting a fully elapsed window as restarted
+    pub fn remaining_budget(&self, now: i64) -> u64 {
+        if now - self.budget_window_start >= self.config.budget_window {
+            return self.config.budget_amount;
+        }
+        self.config.budget_amount.saturating_sub(self.budget_spent)
+    }
+
+    // Restarts the budget window once it has fully elapsed
+    pub fn roll_budget_window(&mut self, now: i64) {
+        if now - self.budget_window_start >= self.config.budget_window {
+            self.budget_window_start = now;
+            self.budget_spent = 0;
+        }
+    }
+
+    // Books an executed action that moved `amount` worth `value` against the totals and the
+    // current budget window
+    pub fn record_execution(
+        &mut self,
+        now: i64,
+        amount: u64,
+        value: u64,
+        health_ratio: u16,
+    ) -> Result<()> {
+        self.last_protection_time = now;
+        self.total_protection_actions += 1;
+        self.total_protected_amount = self
+            .total_protected_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.budget_spent = self
+            .budget_spent
+            .checked_add(value)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.last_health_ratio = health_ratio;
+
+        Ok(())
+    }
+
+    // Notifications start the cooldown like executed actions, so a position below its
+    // trigger cannot be notified again until it passes
+    pub fn record_notification(&mut self, now: i64, health_ratio: u16) {
+        self.last_protection_time = now;
+        self.last_health_ratio = health_ratio;
+    }
+
+    // Seeds the protection account signs with as the owner's delegate
+    pub fn signer_seeds(&self) -> [&[u8]; 5] {
+        [
+            b"protection",
+            self.owner.as_ref(),
+            self.stablecoin_mint.as_ref(),
+            self.collateral_mint.as_ref(),
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    // Claims the next action id for a new action record
+    pub fn open_action_record(&mut self) -> Result<u64> {
+        let action_id = self.next_action_id;
+        self.next_action_id = action_id
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.open_action_records = self
+            .open_action_records
+            .checked_add(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(action_id)
+    }
+
+    pub fn close_action_record(&mut self) -> Result<()> {
+        self.open_action_records = self
+            .open_action_records
+            .checked_sub(1)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+
+        Ok(())
+    }
+
+    // Checks shared by single, batch and simulated execution, in the order they apply, and
+    // the amount protection would move before any source limits
+    pub(crate) fn plan_protection(
+        &self,
+        position: &PositionHealth,
+        health_ratio: u16,
+        action_type: ProtectionActionType,
+        collateral_price: &CollateralPrice,
+        now: i64,
+    ) -> Result<ProtectionPlan> {
+        let skip = if !self.is_active {
+            Some(ProtectionSkip::Inactive)
+        } else if health_ratio >= self.config.trigger_ratio_bps {
+            Some(ProtectionSkip::NotNeeded)
+        } else if !self.config.protection_mode.allows(action_type) {
+            Some(ProtectionSkip::ActionNotAllowed)
+        } else if now - self.last_protection_time < self.config.cooldown_period {
+            Some(ProtectionSkip::CooldownActive)
+        } else {
+            None
+        };
+        if let Some(skip) = skip {
+            return Ok(ProtectionPlan::Skip(skip));
+        }
+
+        // Notification-only protection records the action without moving funds
+        if self.config.notification_only {
+            return Ok(ProtectionPlan::NotifyOnly);
+        }
+
+        let remaining_budget = self.remaining_budget(now);
+        if remaining_budget == 0 {
+            return Ok(ProtectionPlan::Skip(ProtectionSkip::BudgetExhausted));
+        }
+
+        // Enough to bring the position up to the target ratio: collateral tokens to add or
+        // sell, or stablecoins to repay, within the maximum amount and the budget
+        let amount = position
+            .amount_needed(action_type, self.config.target_ratio_bps, collateral_price)?
+            .min(self.config.max_protection_amount)
+            .min(collateral_price.amount_for_value(action_type, remaining_budget)?);
+        Ok(ProtectionPlan::Execute(amount))
+    }
+
+    pub const SPACE: usize = 8
+        + 32
+        + 32
+        + 32
+        + (2 + 2 + 1 + 8 + 1 + 8 + 32 + 8 + 8)
+        + 1
+        + 8
+        + 8
+        + 8
+        + 2
+        + 8
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + 1;
+}
+
+// Why protection does not act on a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ProtectionSkip {
+    Inactive,
+    NotNeeded,        // At or above the trigger ratio
+    ActionNotAllowed, // Not allowed by the protection mode
+    CooldownActive,
+    BudgetExhausted,
+}
+
+impl From<ProtectionSkip> for ErrorCode {
+    fn from(skip: ProtectionSkip) -> Self {
+        match skip {
+            ProtectionSkip::Inactive => ErrorCode::ProtectionInactive,
+            ProtectionSkip::NotNeeded => ErrorCode::ProtectionNotNeeded,
+            ProtectionSkip::ActionNotAllowed => ErrorCode::ActionTypeNotAllowed,
+            ProtectionSkip::CooldownActive => ErrorCode::CooldownPeriodActive,
+            ProtectionSkip::BudgetExhausted => ErrorCode::ProtectionBudgetExhausted,
+        }
+    }
+}
+
+// What execution does for a position, see `ProtectionAccount::plan_protection`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ProtectionPlan {
+    Skip(ProtectionSkip),
+    NotifyOnly,
+    Execute(u64), // Amount to move before any source limits
+}
+
+// What `execute_protection_action` would do right now, returned by `simulate_protection`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ProtectionSimulation {
+    pub health_ratio: u16,                 // Current health ratio in basis points
+    pub would_trigger: bool, // Active, below the trigger, allowed by the mode, out of cooldown and within budget
+    pub action_type: ProtectionActionType, // Action simulated
+    pub amount: u64,         // Amount the action would move, before any source balance limits
+    pub source: Option<Pubkey>, // Token account the amount is drawn from, when fixed by the configuration
+    pub resulting_health_ratio: u16, // Health ratio after the action, at the oracle price
+}
+
+// Protection action history record
 #[account]
 pub struct ProtectionActionRecord {
-    pub protection_account: Pubkey,     // The protection account
-    pub timestamp: i64,                 // When the action occurred
-    pub action_type: u8,                // 1 = Auto-repay, 2 = Add collateral
-    pub amount: u64,                    // Amount involved in the action
-    pub health_ratio_before: u16,       // Health ratio before protection (scaled by 100)
-    pub health_ratio_after: u16,        // Health ratio after protection (scaled by 100)
-    pub success: bool,                  // Whether the protection succeeded
-    pub bump: u8,                       // PDA bump
+    pub protection_account: Pubkey,        // The protection account
+    pub action_id: u64,                    // Sequence number within the protection account
+    pub payer: Pubkey,                     // Paid the rent, refunded when the record is closed
+    pub timestamp: i64,                    // When the action occurred
+    pub action_type: ProtectionActionType, // Action taken
+    pub amount: u64,                       // Amount involved in the action
+    pub health_ratio_before: u16,          // Health ratio before protection in basis points
+    pub health_ratio_after: u16,           // Health ratio after protection in basis points
+    pub success: bool,                     // Whether the protection succeeded
+    pub bump: u8,                          // PDA bump
+    pub version: u8,                       // Layout version, see `PROTECTION_LAYOUT_VERSION`
+}
+
+impl ProtectionActionRecord {
+    pub const SPACE: usize = 8 + 32 + 8 + 32 + 8 + 1 + 8 + 2 + 2 + 1 + 1 + 1;
 }
 
 #[derive(Accounts)]
 pub struct ConfigureProtection<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
-    
+
     pub stablecoin_mint: Account<'info, token::Mint>,
-    
+
     pub collateral_mint: Account<'info, token::Mint>,
-    
+
     #[account(
         init_if_needed,
         payer = owner,
-        space = 8 + 32 + 32 + 32 + 1 + 8 + 1 + 1 + 32 + 1 + 8 + 8 + 8 + 2 + 1, // Adjust as needed
+        space = ProtectionAccount::SPACE,
         seeds = [b"protection", owner.key().as_ref(), stablecoin_mint.key().as_ref(), collateral_mint.key().as_ref()],
         bump,
     )]
     pub protection_account: Account<'info, ProtectionAccount>,
-    
+
+    // The protection account is approved as delegate over this account
     #[account(
+        mut,
         constraint = auto_collateral_source.owner == owner.key(),
         constraint = auto_collateral_source.mint == collateral_mint.key()
     )]
     pub auto_collateral_source: Account<'info, TokenAccount>,
-    
+
+    pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -72,7 +332,7 @@ pub struct ConfigureProtection<'info> {
 pub struct ActivateProtection<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
-    
+
     #[account(
         mut,
         seeds = [b"protection", owner.key().as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
@@ -80,13 +340,41 @@ pub struct ActivateProtection<'info> {
         has_one = owner
     )]
     pub protection_account: Account<'info, ProtectionAccount>,
+
+    #[account(constraint = stablecoin_config.mint == protection_account.stablecoin_mint)]
+    pub stablecoin_config: Box<Account<'info, StablecoinConfig>>,
+
+    #[account(
+        seeds = [b"user-collateral", owner.key().as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_collateral: Box<Account<'info, UserCollateral>>,
+
+    #[account(
+        seeds = [b"user-stablecoin", owner.key().as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_stablecoin: Box<Account<'info, UserStablecoin>>,
+
+    #[account(address = protection_account.collateral_mint)]
+    pub collateral_mint: Box<Account<'info, token::Mint>>,
+
+    // Feed of the collateral, or of the smart vault's underlying for share collateral
+    #[account(
+        seeds = [b"price-feed", collateral_price_feed.mint.as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+
+    // Required when the stablecoin is backed by smart vault shares
+    pub share_collateral_vault: Option<Box<Account<'info, SmartVault>>>,
 }
 
 #[derive(Accounts)]
 pub struct DeactivateProtection<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
-    
+
     #[account(
         mut,
         seeds = [b"protection", owner.key().as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
@@ -98,56 +386,279 @@ pub struct DeactivateProtection<'info> {
 
 #[derive(Accounts)]
 pub struct ExecuteProtectionAction<'info> {
-    // Can be owner or a protection service with delegated authority
+    // The owner or a registered keeper, funds only move through the protection account's delegation
     #[account(mut)]
     pub authority: Signer<'info>,
-    
+
+    // Required unless the owner executes
+    #[account(
+        mut,
+        seeds = [b"keeper", authority.key().as_ref()],
+        bump = keeper.bump,
+        constraint = keeper.active @ ErrorCode::KeeperInactive
+    )]
+    pub keeper: Option<Box<Account<'info, Keeper>>>,
+
+    // Pays the keeper's reward when provided
+    #[account(
+        mut,
+        seeds = [b"keeper-registry"],
+        bump = keeper_registry.bump
+    )]
+    pub keeper_registry: Option<Box<Account<'info, KeeperRegistry>>>,
+
     #[account(
         mut,
         seeds = [b"protection", protection_account.owner.as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
-        bump = protection_account.bump,
-        constraint = protection_account.is_active == true
+        bump = protection_account.bump
     )]
     pub protection_account: Account<'info, ProtectionAccount>,
-    
+
+    #[account(
+        mut,
+        constraint = stablecoin_config.mint == protection_account.stablecoin_mint
+    )]
+    pub stablecoin_config: Box<Account<'info, StablecoinConfig>>,
+
+    #[account(
+        seeds = [b"stablecoin-vault", stablecoin_config.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA that holds the stablecoin's collateral
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"user-collateral", protection_account.owner.as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_collateral: Box<Account<'info, UserCollateral>>,
+
+    #[account(
+        mut,
+        seeds = [b"user-stablecoin", protection_account.owner.as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_stablecoin: Box<Account<'info, UserStablecoin>>,
+
+    #[account(address = protection_account.collateral_mint)]
+    pub collateral_mint: Box<Account<'info, token::Mint>>,
+
+    // Feed of the collateral, or of the smart vault's underlying for share collateral
+    #[account(
+        seeds = [b"price-feed", collateral_price_feed.mint.as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+
+    // Required when the stablecoin is backed by smart vault shares
+    pub share_collateral_vault: Option<Box<Account<'info, SmartVault>>>,
+
+    // Required for auto-repay
+    #[account(mut, address = protection_account.stablecoin_mint)]
+    pub stablecoin_mint: Option<Box<Account<'info, token::Mint>>>,
+
+    // Owner's stablecoins burned by auto-repay, with the protection account approved as delegate.
+    // Deleverage proceeds beyond the debt are returned here.
+    #[account(
+        mut,
+        constraint = repay_source_token_account.mint == protection_account.stablecoin_mint,
+        constraint = repay_source_token_account.owner == protection_account.owner
+    )]
+    pub repay_source_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
     #[account(
         mut,
         constraint = source_token_account.mint == protection_account.collateral_mint,
-        constraint = source_token_account.owner == authority.key() || source_token_account.key() == protection_account.config.auto_collateral_source
+        constraint = source_token_account.key() == protection_account.config.auto_collateral_source
     )]
     pub source_token_account: Account<'info, TokenAccount>,
-    
+
+    // Required for unwinding a smart vault, whose underlying must be the protected collateral
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        constraint = smart_vault.collateral_mint == protection_account.collateral_mint
+    )]
+    pub smart_vault: Option<Box<Account<'info, SmartVault>>>,
+
+    #[account(mut)]
+    pub share_mint: Option<Box<Account<'info, token::Mint>>>,
+
+    // Owner's vault shares, with the protection account approved as delegate
+    #[account(
+        mut,
+        constraint = owner_share_account.owner == protection_account.owner
+    )]
+    pub owner_share_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub vault_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Required for deleveraging, along with the stablecoin mint. Swap program specific
+    // accounts are passed as remaining accounts.
+    pub swap_adapter: Option<Box<Account<'info, SwapAdapter>>>,
+
+    /// CHECK: Registered through its swap adapter account
+    #[account(executable)]
+    pub swap_program: Option<UncheckedAccount<'info>>,
+
+    // Receives the stablecoins the sold collateral is swapped into, before they are burned
+    #[account(
+        mut,
+        constraint = deleverage_proceeds_token_account.mint == protection_account.stablecoin_mint,
+        constraint = deleverage_proceeds_token_account.owner == stablecoin_vault.key()
+    )]
+    pub deleverage_proceeds_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    // Collateral added by protection is held by the stablecoin vault
     #[account(
         mut,
-        // In a real implementation, this would be the vault or position token account
-        constraint = destination_token_account.mint == protection_account.collateral_mint
+        constraint = destination_token_account.mint == protection_account.collateral_mint,
+        constraint = destination_token_account.owner == stablecoin_vault.key()
     )]
     pub destination_token_account: Account<'info, TokenAccount>,
-    
+
     // Record of this protection action
     #[account(
         init,
         payer = authority,
-        space = 8 + 32 + 8 + 1 + 8 + 2 + 2 + 1 + 1,
-        seeds = [b"protection-action", protection_account.key().as_ref()],
+        space = ProtectionActionRecord::SPACE,
+        seeds = [b"protection-action", protection_account.key().as_ref(), protection_account.next_action_id.to_le_bytes().as_ref()],
         bump
     )]
     pub action_record: Account<'info, ProtectionActionRecord>,
-    
+
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
     pub clock: Sysvar<'info, Clock>,
 }
 
+#[derive(Accounts)]
+pub struct CloseProtectionActionRecord<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protection", owner.key().as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
+        bump = protection_account.bump,
+        has_one = owner
+    )]
+    pub protection_account: Account<'info, ProtectionAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"protection-action", protection_account.key().as_ref(), action_record.action_id.to_le_bytes().as_ref()],
+        bump = action_record.bump,
+        has_one = protection_account,
+        has_one = payer,
+        close = payer
+    )]
+    pub action_record: Account<'info, ProtectionActionRecord>,
+
+    /// CHECK: Receives the record's rent, checked against the record
+    #[account(mut)]
+    pub payer: UncheckedAccount<'info>,
+}
+
+// Other token accounts the protection account is delegate of, and action records to close,
+// are passed as remaining accounts. Each action record is followed by its payer.
+#[derive(Accounts)]
+pub struct CloseProtection<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protection", owner.key().as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
+        bump = protection_account.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub protection_account: Account<'info, ProtectionAccount>,
+
+    // Optional in case the owner has already closed it
+    #[account(mut, address = protection_account.config.auto_collateral_source)]
+    pub auto_collateral_source: Option<Box<Account<'info, TokenAccount>>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Read-only: the owner's position is looked up from the protection account
+#[derive(Accounts)]
+pub struct SimulateProtection<'info> {
+    #[account(
+        seeds = [b"protection", protection_account.owner.as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
+        bump = protection_account.bump
+    )]
+    pub protection_account: Account<'info, ProtectionAccount>,
+
+    #[account(constraint = stablecoin_config.mint == protection_account.stablecoin_mint)]
+    pub stablecoin_config: Box<Account<'info, StablecoinConfig>>,
+
+    #[account(
+        seeds = [b"stablecoin-vault", stablecoin_config.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA that holds the stablecoin's collateral
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"user-collateral", protection_account.owner.as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_collateral: Box<Account<'info, UserCollateral>>,
+
+    #[account(
+        seeds = [b"user-stablecoin", protection_account.owner.as_ref(), stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub user_stablecoin: Box<Account<'info, UserStablecoin>>,
+
+    #[account(address = protection_account.collateral_mint)]
+    pub collateral_mint: Box<Account<'info, token::Mint>>,
+
+    // Feed of the collateral, or of the smart vault's underlying for share collateral
+    #[account(
+        seeds = [b"price-feed", collateral_price_feed.mint.as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+
+    // Required when the stablecoin is backed by smart vault shares
+    pub share_collateral_vault: Option<Box<Account<'info, SmartVault>>>,
+
+    // Required for deleveraging: the stablecoin vault's collateral, which deleveraging sells
+    #[account(
+        constraint = vault_collateral_token_account.mint == protection_account.collateral_mint,
+        constraint = vault_collateral_token_account.owner == stablecoin_vault.key()
+    )]
+    pub vault_collateral_token_account: Option<Box<Account<'info, TokenAccount>>>,
+}
+
 // Implementation of the functions
 
-pub fn configure_protection(ctx: Context<ConfigureProtection>, config: ProtectionConfig) -> Result<()> {
+pub fn configure_protection(
+    ctx: Context<ConfigureProtection>,
+    config: ProtectionConfig,
+) -> Result<()> {
     let protection_account = &mut ctx.accounts.protection_account;
-    
+
     // Validate config parameters
-    require!(config.threshold_percentage > 100, ErrorCode::InvalidThreshold);
-    require!(config.protection_mode > 0 && config.protection_mode <= 3, ErrorCode::InvalidProtectionMode);
-    
+    require!(
+        config.trigger_ratio_bps as u64 > HEALTH_RATIO_SCALE,
+        ErrorCode::InvalidThreshold
+    );
+    require!(
+        config.target_ratio_bps > config.trigger_ratio_bps,
+        ErrorCode::InvalidTargetRatio
+    );
+    require!(
+        config.budget_amount > 0 && config.budget_window > 0,
+        ErrorCode::InvalidBudget
+    );
+
     // Initialize or update protection account
     if protection_account.owner == Pubkey::default() {
         protection_account.owner = ctx.accounts.owner.key();
@@ -158,75 +669,115 @@ pub fn configure_protection(ctx: Context<ConfigureProtection>, config: Protectio
         protection_account.total_protection_actions = 0;
         protection_account.total_protected_amount = 0;
         protection_account.last_health_ratio = 0;
-        // Set a fixed bump value for now until we resolve the build issues
-        protection_account.bump = 255;
+        protection_account.next_action_id = 0;
+        protection_account.open_action_records = 0;
+        protection_account.budget_window_start = 0;
+        protection_account.budget_spent = 0;
+        protection_account.custodied_collateral = 0;
+        protection_account.bump = ctx.bumps.protection_account;
+        protection_account.version = PROTECTION_LAYOUT_VERSION;
     }
-    
+
+    // Approve the protection account to move up to the maximum protection amount
+    let approve_instruction = Approve {
+        to: ctx.accounts.auto_collateral_source.to_account_info(),
+        delegate: protection_account.to_account_info(),
+        authority: ctx.accounts.owner.to_account_info(),
+    };
+
+    token::approve(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            approve_instruction,
+        ),
+        config.max_protection_amount,
+    )?;
+
     // Update config
     protection_account.config = config;
-    
+
     Ok(())
 }
 
 pub fn activate_protection(ctx: Context<ActivateProtection>) -> Result<()> {
+    let collateral_price = CollateralPrice::load(
+        &ctx.accounts.stablecoin_config,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.share_collateral_vault.as_deref(),
+    )?;
+    let position = PositionHealth::load(
+        &ctx.accounts.user_collateral,
+        &ctx.accounts.user_stablecoin,
+        &collateral_price,
+    )?;
+
     let protection_account = &mut ctx.accounts.protection_account;
-    
     protection_account.is_active = true;
-    protection_account.last_health_ratio = get_current_health_ratio()?; // Mock function
-    
+    protection_account.last_health_ratio = position.health_ratio()?;
+
     Ok(())
 }
 
 pub fn deactivate_protection(ctx: Context<DeactivateProtection>) -> Result<()> {
     let protection_account = &mut ctx.accounts.protection_account;
-    
+
     protection_account.is_active = false;
-    
+
     Ok(())
 }
 
-pub fn execute_protection_action(ctx: Context<ExecuteProtectionAction>, action_type: u8) -> Result<()> {
+pub fn execute_protection_action<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteProtectionAction<'info>>,
+    action_type: ProtectionActionType,
+) -> Result<()> {
+    let action_bump = ctx.bumps.action_record;
+    let stablecoin_vault_bump = ctx.bumps.stablecoin_vault;
+    let payer = ctx.accounts.authority.key();
     let protection_account = &mut ctx.accounts.protection_account;
     let action_record = &mut ctx.accounts.action_record;
     let current_time = Clock::get()?.unix_timestamp;
-    
-    // Verify cooldown period
-    require!(
-        current_time - protection_account.last_protection_time >= protection_account.config.cooldown_period,
-        ErrorCode::CooldownPeriodActive
-    );
-    
+
+    let executed_by_keeper = executed_by_keeper(
+        payer,
+        protection_account.owner,
+        ctx.accounts.keeper.is_some(),
+    )?;
+
     // Get current health ratio
-    let current_health_ratio = get_current_health_ratio()?;
-    
-    // Check if protection is needed
-    require!(
-        current_health_ratio < protection_account.config.threshold_percentage as u16,
-        ErrorCode::ProtectionNotNeeded
-    );
-    
-    // Verify action type matches configured mode
-    match action_type {
-        1 => require!(
-            protection_account.config.protection_mode == 1 || protection_account.config.protection_mode == 3,
-            ErrorCode::ActionTypeNotAllowed
-        ),
-        2 => require!(
-            protection_account.config.protection_mode == 2 || protection_account.config.protection_mode == 3,
-            ErrorCode::ActionTypeNotAllowed
-        ),
-        _ => return Err(ErrorCode::InvalidActionType.into()),
-    }
-    
+    let collateral_price = CollateralPrice::load(
+        &ctx.accounts.stablecoin_config,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.share_collateral_vault.as_deref(),
+    )?;
+    let position = PositionHealth::load(
+        &ctx.accounts.user_collateral,
+        &ctx.accounts.user_stablecoin,
+        &collateral_price,
+    )?;
+    let current_health_ratio = position.health_ratio()?;
+
+    let plan = protection_account.plan_protection(
+        &position,
+        current_health_ratio,
+        action_type,
+        &collateral_price,
+        current_time,
+    )?;
+    let mut protection_amount = match plan {
+        ProtectionPlan::Skip(skip) => return Err(ErrorCode::from(skip).into()),
+        ProtectionPlan::Execute(amount) => amount,
+        ProtectionPlan::NotifyOnly => 0,
+    };
+
     // Skip actual protection if notification_only is true
-    if protection_account.config.notification_only {
+    if plan == ProtectionPlan::NotifyOnly {
         // Just record the action without executing
-        // Set a fixed bump value for now
-        let action_bump = 255;
-        
-        record_protection_action(
-            action_record,
-            protection_account.key(),
+        protection_account.record_notification(current_time, current_health_ratio);
+        action_record.set_inner(new_action_record(
+            protection_account,
+            payer,
             current_time,
             action_type,
             0,
@@ -234,60 +785,316 @@ pub fn execute_protection_action(ctx: Context<ExecuteProtectionAction>, action_t
             current_health_ratio,
             false,
             action_bump,
-        )?;
-        
+        )?);
+
         return Ok(());
     }
-    
-    // Calculate amount needed for protection
-    let protection_amount = calculate_protection_amount(current_health_ratio, protection_account.config.threshold_percentage)?;
-    
-    // Limit to max protection amount
-    let protection_amount = std::cmp::min(protection_amount, protection_account.config.max_protection_amount);
-    
-    // Check if source has enough funds
-    require!(
-        ctx.accounts.source_token_account.amount >= protection_amount,
-        ErrorCode::InsufficientFunds
-    );
-    
+
+    protection_account.roll_budget_window(current_time);
+    let mut custodied_collateral = protection_account.custodied_collateral;
+
+    if action_type == ProtectionActionType::AutoRepay {
+        let (Some(stablecoin_mint), Some(repay_source_token_account)) = (
+            &ctx.accounts.stablecoin_mint,
+            &ctx.accounts.repay_source_token_account,
+        ) else {
+            return Err(ErrorCode::MissingRepayAccounts.into());
+        };
+
+        check_delegated_source(
+            repay_source_token_account,
+            protection_account.key(),
+            protection_amount,
+        )?;
+
+        // Burn the owner's stablecoins
+        burn_as_delegate(
+            protection_account,
+            stablecoin_mint,
+            repay_source_token_account,
+            &ctx.accounts.token_program,
+            protection_amount,
+        )?;
+
+        let user_stablecoin = &mut ctx.accounts.user_stablecoin;
+        user_stablecoin.amount = user_stablecoin
+            .amount
+            .checked_sub(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let stablecoin_config = &mut ctx.accounts.stablecoin_config;
+        stablecoin_config.total_supply = stablecoin_config
+            .total_supply
+            .checked_sub(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
     // Execute the transfer - using token::transfer to add funds
-    if action_type == 2 { // Add collateral
+    if action_type == ProtectionActionType::AddCollateral {
+        let source_token_account = &ctx.accounts.source_token_account;
+
+        // Check if source has enough funds
+        check_delegated_source(
+            source_token_account,
+            protection_account.key(),
+            protection_amount,
+        )?;
+
         // Transfer tokens from source to destination (typically the collateral position)
-        let transfer_instruction = Transfer {
-            from: ctx.accounts.source_token_account.to_account_info(),
-            to: ctx.accounts.destination_token_account.to_account_info(),
-            authority: ctx.accounts.authority.to_account_info(),
+        transfer_as_delegate(
+            protection_account,
+            source_token_account,
+            &ctx.accounts.destination_token_account,
+            &ctx.accounts.token_program,
+            protection_amount,
+        )?;
+
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        user_collateral.amount = user_collateral
+            .amount
+            .checked_add(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        custodied_collateral = custodied_collateral
+            .checked_add(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    if action_type == ProtectionActionType::UnwindSmartVault {
+        let (
+            Some(smart_vault),
+            Some(share_mint),
+            Some(owner_share_account),
+            Some(vault_token_account),
+        ) = (
+            &mut ctx.accounts.smart_vault,
+            &ctx.accounts.share_mint,
+            &ctx.accounts.owner_share_account,
+            &ctx.accounts.vault_token_account,
+        )
+        else {
+            return Err(ErrorCode::MissingSmartVaultAccounts.into());
         };
-        
-        token::transfer(
-            CpiContext::new(
+        require!(
+            share_mint.key() == smart_vault.share_mint,
+            ErrorCode::InvalidSmartVaultAccounts
+        );
+        require!(
+            owner_share_account.mint == smart_vault.share_mint,
+            ErrorCode::InvalidSmartVaultAccounts
+        );
+        require!(
+            vault_token_account.key() == smart_vault.vault_token_account,
+            ErrorCode::InvalidSmartVaultAccounts
+        );
+
+        // Only idle funds can be withdrawn immediately, up to what the owner's shares are worth
+        let redeemable = if share_mint.supply == 0 {
+            0
+        } else {
+            mul_div(
+                owner_share_account.amount,
+                smart_vault.total_deposited,
+                share_mint.supply,
+            )?
+        };
+        protection_amount = protection_amount
+            .min(smart_vault.free_idle_balance(vault_token_account))
+            .min(redeemable);
+        require!(protection_amount > 0, ErrorCode::InsufficientFunds);
+
+        let shares = smart_vault.shares_for_withdrawal(protection_amount, share_mint.supply)?;
+        require!(
+            owner_share_account.delegate == COption::Some(protection_account.key())
+                && owner_share_account.delegated_amount >= shares,
+            ErrorCode::InsufficientDelegation
+        );
+
+        // Burn the owner's shares and send the proceeds straight to the position's collateral
+        burn_as_delegate(
+            protection_account,
+            share_mint,
+            owner_share_account,
+            &ctx.accounts.token_program,
+            shares,
+        )?;
+
+        transfer_from_vault(
+            smart_vault,
+            vault_token_account,
+            &ctx.accounts.destination_token_account,
+            &ctx.accounts.token_program,
+            protection_amount,
+        )?;
+
+        smart_vault.total_deposited = smart_vault
+            .total_deposited
+            .checked_sub(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        smart_vault.last_update_time = current_time;
+
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        user_collateral.amount = user_collateral
+            .amount
+            .checked_add(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        custodied_collateral = custodied_collateral
+            .checked_add(protection_amount)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    if action_type == ProtectionActionType::Deleverage {
+        let (
+            Some(stablecoin_mint),
+            Some(swap_adapter),
+            Some(swap_program),
+            Some(proceeds_token_account),
+        ) = (
+            &ctx.accounts.stablecoin_mint,
+            &ctx.accounts.swap_adapter,
+            &ctx.accounts.swap_program,
+            &mut ctx.accounts.deleverage_proceeds_token_account,
+        )
+        else {
+            return Err(ErrorCode::MissingDeleverageAccounts.into());
+        };
+        require!(
+            swap_adapter.program_id == swap_program.key(),
+            ErrorCode::UnregisteredSwapProgram
+        );
+        // Vault shares are redeemed through the vault rather than sold
+        require!(
+            !collateral_price.is_share_collateral,
+            ErrorCode::ActionTypeNotAllowed
+        );
+
+        // The stablecoin vault pools every position's collateral, so only what protection
+        // has moved into it for this position can be sold
+        protection_amount = protection_amount
+            .min(ctx.accounts.user_collateral.amount)
+            .min(custodied_collateral)
+            .min(ctx.accounts.destination_token_account.amount);
+        require!(protection_amount > 0, ErrorCode::InsufficientFunds);
+
+        // Bound the swap by the collateral's oracle value, stablecoins being pegged to $1
+        let oracle_value = collateral_price.value_of(protection_amount)?;
+        let min_amount_out = mul_div(
+            oracle_value,
+            (MAX_BPS - DELEVERAGE_MAX_SLIPPAGE_BPS) as u64,
+            MAX_BPS as u64,
+        )?;
+
+        let stablecoin_config_key = ctx.accounts.stablecoin_config.key();
+        let vault_seeds = &[
+            b"stablecoin-vault",
+            stablecoin_config_key.as_ref(),
+            &[stablecoin_vault_bump],
+        ];
+        let (spent, received) = swap_exact_in(
+            swap_program,
+            &ctx.accounts.stablecoin_vault.to_account_info(),
+            &mut ctx.accounts.destination_token_account,
+            proceeds_token_account,
+            &ctx.accounts.token_program,
+            ctx.remaining_accounts,
+            protection_amount,
+            min_amount_out,
+            &[&vault_seeds[..]],
+        )?;
+
+        // Repay debt with the proceeds
+        let (repaid, surplus) = position.repayment_from_proceeds(received);
+        let burn_instruction = Burn {
+            mint: stablecoin_mint.to_account_info(),
+            from: proceeds_token_account.to_account_info(),
+            authority: ctx.accounts.stablecoin_vault.to_account_info(),
+        };
+
+        token::burn(
+            CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                transfer_instruction,
+                burn_instruction,
+                &[&vault_seeds[..]],
             ),
-            protection_amount,
+            repaid,
         )?;
+
+        // Collateral sold from an underwater position can fetch more than the debt. The
+        // surplus was bought rather than minted, so it goes to the owner as is.
+        if surplus > 0 {
+            let Some(owner_stablecoin_account) = &ctx.accounts.repay_source_token_account else {
+                return Err(ErrorCode::MissingSurplusAccount.into());
+            };
+
+            let transfer_instruction = Transfer {
+                from: proceeds_token_account.to_account_info(),
+                to: owner_stablecoin_account.to_account_info(),
+                authority: ctx.accounts.stablecoin_vault.to_account_info(),
+            };
+
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    transfer_instruction,
+                    &[&vault_seeds[..]],
+                ),
+                surplus,
+            )?;
+        }
+
+        let user_collateral = &mut ctx.accounts.user_collateral;
+        user_collateral.amount = user_collateral
+            .amount
+            .checked_sub(spent)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        custodied_collateral = custodied_collateral
+            .checked_sub(spent)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        protection_amount = spent;
+        let user_stablecoin = &mut ctx.accounts.user_stablecoin;
+        user_stablecoin.amount = user_stablecoin
+            .amount
+            .checked_sub(repaid)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        let stablecoin_config = &mut ctx.accounts.stablecoin_config;
+        stablecoin_config.total_supply = stablecoin_config
+            .total_supply
+            .checked_sub(repaid)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
     }
-    
-    // For action_type == 1 (Auto-repay), we would implement repayment logic here
-    // This is intentionally simplified for this demo
-    
-    // Calculate new health ratio after protection
-    let new_health_ratio = calculate_new_health_ratio(current_health_ratio, protection_amount)?;
-    
+
+    // Calculate new health ratio from the updated balances
+    let new_health_ratio = PositionHealth::load(
+        &ctx.accounts.user_collateral,
+        &ctx.accounts.user_stablecoin,
+        &collateral_price,
+    )?
+    .health_ratio()?;
+
     // Update protection account
-    protection_account.last_protection_time = current_time;
-    protection_account.total_protection_actions += 1;
-    protection_account.total_protected_amount = protection_account.total_protected_amount.checked_add(protection_amount).unwrap();
-    protection_account.last_health_ratio = new_health_ratio;
-    
+    let value_moved = collateral_price.value_moved(action_type, protection_amount)?;
+    protection_account.custodied_collateral = custodied_collateral;
+    protection_account.record_execution(
+        current_time,
+        protection_amount,
+        value_moved,
+        new_health_ratio,
+    )?;
+
+    // Compensate the keeper from the protocol fund
+    if executed_by_keeper {
+        let reward = reward_executing_keeper(
+            ctx.accounts.keeper.as_deref_mut(),
+            ctx.accounts.keeper_registry.as_deref_mut(),
+            &ctx.accounts.authority.to_account_info(),
+            value_moved,
+            protection_account.config.cooldown_period,
+        )?;
+        msg!("Keeper {} rewarded {} lamports", payer, reward);
+    }
+
     // Record the action
-    // Set a fixed bump value for now
-    let action_bump = 255;
-    
-    record_protection_action(
-        action_record,
-        protection_account.key(),
+    action_record.set_inner(new_action_record(
+        protection_account,
+        payer,
         current_time,
         action_type,
         protection_amount,
@@ -295,82 +1102,1004 @@ pub fn execute_protection_action(ctx: Context<ExecuteProtectionAction>, action_t
         new_health_ratio,
         true,
         action_bump,
-    )?;
-    
+    )?);
+
+    Ok(())
+}
+
+// Records are closed individually once they are no longer needed, refunding whoever paid for them
+pub fn close_protection_action_record(ctx: Context<CloseProtectionActionRecord>) -> Result<()> {
+    ctx.accounts.protection_account.close_action_record()
+}
+
+// Revokes the program's delegations and closes the protection account to its owner, along
+// with the action records passed in. Every record must be closed by then: a reopened account
+// starts its action ids again, which would collide with records left behind.
+pub fn close_protection<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseProtection<'info>>,
+) -> Result<()> {
+    let protection_key = ctx.accounts.protection_account.key();
+    let owner = ctx.accounts.owner.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+
+    if let Some(auto_collateral_source) = &ctx.accounts.auto_collateral_source {
+        revoke_protection_delegation(
+            auto_collateral_source,
+            protection_key,
+            &owner,
+            &token_program,
+        )?;
+    }
+
+    let mut remaining_accounts = ctx.remaining_accounts.iter();
+    while let Some(account) = remaining_accounts.next() {
+        if account.owner == &token::ID {
+            let token_account = Account::<TokenAccount>::try_from(account)?;
+            require_keys_eq!(
+                token_account.owner,
+                owner.key(),
+                ErrorCode::InvalidCloseAccounts
+            );
+            revoke_protection_delegation(&token_account, protection_key, &owner, &token_program)?;
+        } else {
+            let action_record = Account::<ProtectionActionRecord>::try_from(account)?;
+            require_keys_eq!(
+                action_record.protection_account,
+                protection_key,
+                ErrorCode::InvalidCloseAccounts
+            );
+            let payer = remaining_accounts
+                .next()
+                .ok_or(ErrorCode::InvalidCloseAccounts)?;
+            require_keys_eq!(
+                payer.key(),
+                action_record.payer,
+                ErrorCode::InvalidCloseAccounts
+            );
+            action_record.close(payer.clone())?;
+            ctx.accounts.protection_account.close_action_record()?;
+        }
+    }
+
+    require!(
+        ctx.accounts.protection_account.open_action_records == 0,
+        ErrorCode::ActionRecordsOpen
+    );
+
     Ok(())
 }
 
+pub fn simulate_protection(
+    ctx: Context<SimulateProtection>,
+    action_type: ProtectionActionType,
+) -> Result<ProtectionSimulation> {
+    let protection_account = &ctx.accounts.protection_account;
+    let current_time = Clock::get()?.unix_timestamp;
+
+    let collateral_price = CollateralPrice::load(
+        &ctx.accounts.stablecoin_config,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.share_collateral_vault.as_deref(),
+    )?;
+    let position = PositionHealth::load(
+        &ctx.accounts.user_collateral,
+        &ctx.accounts.user_stablecoin,
+        &collateral_price,
+    )?;
+    let health_ratio = position.health_ratio()?;
+
+    // Same checks and limits as execution; notification-only protection never moves funds
+    let plan = protection_account.plan_protection(
+        &position,
+        health_ratio,
+        action_type,
+        &collateral_price,
+        current_time,
+    )?;
+    let would_trigger = !matches!(plan, ProtectionPlan::Skip(_));
+    let mut amount = match plan {
+        ProtectionPlan::Execute(amount) => amount,
+        ProtectionPlan::Skip(_) | ProtectionPlan::NotifyOnly => 0,
+    };
+    if action_type == ProtectionActionType::Deleverage {
+        amount = amount
+            .min(ctx.accounts.user_collateral.amount)
+            .min(protection_account.custodied_collateral);
+    }
+
+    let source = match action_type {
+        ProtectionActionType::AddCollateral => {
+            Some(protection_account.config.auto_collateral_source)
+        }
+        ProtectionActionType::Deleverage => Some(
+            ctx.accounts
+                .vault_collateral_token_account
+                .as_ref()
+                .ok_or(ErrorCode::MissingDeleverageAccounts)?
+                .key(),
+        ),
+        ProtectionActionType::AutoRepay | ProtectionActionType::UnwindSmartVault => None,
+    };
+
+    let resulting_health_ratio = position
+        .after(action_type, amount, &collateral_price)?
+        .health_ratio()?;
+
+    Ok(ProtectionSimulation {
+        health_ratio,
+        would_trigger,
+        action_type,
+        amount,
+        source,
+        resulting_health_ratio,
+    })
+}
+
 // Helper functions
 
-fn record_protection_action(
-    action_record: &mut ProtectionActionRecord,
+// Only registered keepers may act on someone else's position. Returns whether the authority
+// is acting as a keeper.
+pub(crate) fn executed_by_keeper(
+    authority: Pubkey,
+    owner: Pubkey,
+    keeper_registered: bool,
+) -> Result<bool> {
+    let executed_by_keeper = authority != owner;
+    require!(
+        !executed_by_keeper || keeper_registered,
+        ErrorCode::UnregisteredKeeper
+    );
+    Ok(executed_by_keeper)
+}
+
+// Compensates a keeper from the protocol fund, when a registry is passed to pay from
+pub(crate) fn reward_executing_keeper<'info>(
+    keeper: Option<&mut Account<'info, Keeper>>,
+    keeper_registry: Option<&mut Account<'info, KeeperRegistry>>,
+    authority: &AccountInfo<'info>,
+    value_moved: u64,
+    cooldown_period: i64,
+) -> Result<u64> {
+    match (keeper, keeper_registry) {
+        (Some(keeper), Some(keeper_registry)) => reward_keeper(
+            keeper_registry,
+            keeper,
+            authority,
+            value_moved,
+            cooldown_period,
+        ),
+        _ => Ok(0),
+    }
+}
+
+// Revokes the owner's approval, unless it has since been given to someone else
+fn revoke_protection_delegation<'info>(
+    token_account: &Account<'info, TokenAccount>,
+    protection_account: Pubkey,
+    owner: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if token_account.delegate != COption::Some(protection_account) {
+        return Ok(());
+    }
+
+    let revoke_instruction = Revoke {
+        source: token_account.to_account_info(),
+        authority: owner.clone(),
+    };
+
+    token::revoke(CpiContext::new(token_program.clone(), revoke_instruction))
+}
+
+// Funds only move out of an owner's account through the protection account's delegation.
+// Shared by single and batch execution, which reports a failure instead of returning it.
+pub(crate) fn check_delegated_source(
+    source: &spl_token::state::Account,
     protection_account: Pubkey,
+    amount: u64,
+) -> Result<()> {
+    require!(source.amount >= amount, ErrorCode::InsufficientFunds);
+    require!(
+        source.delegate == COption::Some(protection_account) && source.delegated_amount >= amount,
+        ErrorCode::InsufficientDelegation
+    );
+
+    Ok(())
+}
+
+// Burns the owner's tokens with the protection account signing as their delegate
+pub(crate) fn burn_as_delegate<'info>(
+    protection_account: &Account<'info, ProtectionAccount>,
+    mint: &Account<'info, token::Mint>,
+    source: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let burn_instruction = Burn {
+        mint: mint.to_account_info(),
+        from: source.to_account_info(),
+        authority: protection_account.to_account_info(),
+    };
+
+    token::burn(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            burn_instruction,
+            &[&protection_account.signer_seeds()[..]],
+        ),
+        amount,
+    )
+}
+
+// Transfers the owner's tokens with the protection account signing as their delegate
+pub(crate) fn transfer_as_delegate<'info>(
+    protection_account: &Account<'info, ProtectionAccount>,
+    source: &Account<'info, TokenAccount>,
+    destination: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let transfer_instruction = Transfer {
+        from: source.to_account_info(),
+        to: destination.to_account_info(),
+        authority: protection_account.to_account_info(),
+    };
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            transfer_instruction,
+            &[&protection_account.signer_seeds()[..]],
+        ),
+        amount,
+    )
+}
+
+// The record of an action on `protection_account` under its next action id, shared by
+// single and batch execution
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn new_action_record(
+    protection_account: &mut Account<ProtectionAccount>,
+    payer: Pubkey,
     timestamp: i64,
-    action_type: u8,
+    action_type: ProtectionActionType,
     amount: u64,
     health_ratio_before: u16,
     health_ratio_after: u16,
     success: bool,
     bump: u8,
-) -> Result<()> {
-    action_record.protection_account = protection_account;
-    action_record.timestamp = timestamp;
-    action_record.action_type = action_type;
-    action_record.amount = amount;
-    action_record.health_ratio_before = health_ratio_before;
-    action_record.health_ratio_after = health_ratio_after;
-    action_record.success = success;
-    action_record.bump = bump;
-    
-    Ok(())
+) -> Result<ProtectionActionRecord> {
+    Ok(ProtectionActionRecord {
+        protection_account: protection_account.key(),
+        action_id: protection_account.open_action_record()?,
+        payer,
+        timestamp,
+        action_type,
+        amount,
+        health_ratio_before,
+        health_ratio_after,
+        success,
+        bump,
+        version: PROTECTION_LAYOUT_VERSION,
+    })
+}
+
+// Collateral value and stablecoin debt of a protected position, both in the oracle's
+// quote unit. Stablecoins have 6 decimals and are pegged to $1, so debt in base units
+// is already scaled by PRICE_SCALE.
+pub(crate) struct PositionHealth {
+    pub collateral_value: u64,
+    pub debt: u64,
 }
 
-// In a real implementation, these would interact with oracle price feeds and position data
-// For demo purposes, these are simplified mock implementations
+impl PositionHealth {
+    pub fn load(
+        user_collateral: &UserCollateral,
+        user_stablecoin: &UserStablecoin,
+        collateral_price: &CollateralPrice,
+    ) -> Result<Self> {
+        Ok(Self {
+            collateral_value: collateral_price.value_of(user_collateral.amount)?,
+            debt: user_stablecoin.amount,
+        })
+    }
+
+    // Whether the collateral value covers the debt at `collateralization_ratio`, in basis points
+    pub fn is_collateralized(&self, collateralization_ratio: u64) -> bool {
+        self.collateral_value as u128 * HEALTH_RATIO_SCALE as u128
+            >= self.debt as u128 * collateralization_ratio as u128
+    }
+
+    // Collateral value over debt in basis points, saturating for debt-free positions
+    pub fn health_ratio(&self) -> Result<u16> {
+        if self.debt == 0 {
+            return Ok(u16::MAX);
+        }
+        let ratio = (self.collateral_value as u128)
+            .checked_mul(HEALTH_RATIO_SCALE as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / self.debt as u128;
+
+        Ok(ratio.min(u16::MAX as u128) as u16)
+    }
+
+    // Amount an action needs to bring the position up to the target ratio: collateral
+    // tokens to add or sell, or stablecoins to repay
+    pub fn amount_needed(
+        &self,
+        action_type: ProtectionActionType,
+        target_ratio: u16,
+        collateral_price: &CollateralPrice,
+    ) -> Result<u64> {
+        match action_type {
+            ProtectionActionType::AutoRepay => self.repayment_needed(target_ratio),
+            ProtectionActionType::Deleverage => {
+                self.deleverage_needed(target_ratio, collateral_price)
+            }
+            ProtectionActionType::AddCollateral | ProtectionActionType::UnwindSmartVault => {
+                self.collateral_needed(target_ratio, collateral_price)
+            }
+        }
+    }
+
+    // The position once an action has moved `amount`, with collateral at the oracle price
+    pub fn after(
+        &self,
+        action_type: ProtectionActionType,
+        amount: u64,
+        collateral_price: &CollateralPrice,
+    ) -> Result<Self> {
+        let (collateral_value, debt) = match action_type {
+            ProtectionActionType::AutoRepay => {
+                (self.collateral_value, self.debt.saturating_sub(amount))
+            }
+            ProtectionActionType::AddCollateral | ProtectionActionType::UnwindSmartVault => {
+                let value = collateral_price.value_of(amount)?;
+                (
+                    self.collateral_value
+                        .checked_add(value)
+                        .ok_or(ErrorCode::ArithmeticOverflow)?,
+                    self.debt,
+                )
+            }
+            ProtectionActionType::Deleverage => {
+                let value = collateral_price.value_of(amount)?;
+                (
+                    self.collateral_value.saturating_sub(value),
+                    self.debt.saturating_sub(value),
+                )
+            }
+        };
+
+        Ok(Self {
+            collateral_value,
+            debt,
+        })
+    }
+
+    // Collateral tokens to add so that value >= debt * target / 10_000, rounded up
+    pub fn collateral_needed(
+        &self,
+        target_ratio: u16,
+        collateral_price: &CollateralPrice,
+    ) -> Result<u64> {
+        let required_value = (self.debt as u128)
+            .checked_mul(target_ratio as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .div_ceil(HEALTH_RATIO_SCALE as u128);
+        let shortfall = required_value.saturating_sub(self.collateral_value as u128);
+
+        collateral_price.tokens_for(shortfall)
+    }
 
-// Gets the current health ratio from the oracle/position
-fn get_current_health_ratio() -> Result<u16> {
-    // Mock implementation - would retrieve from oracle in real implementation
-    // Returns random health ratio between 105-125% for demo purposes
-    let mock_health_ratio = 110u16;
-    Ok(mock_health_ratio)
+    // Collateral tokens to sell and repay as debt so that the rest reaches the target,
+    // rounded up. Selling value v gives (collateral - v) / (debt - v) = target, capped at the
+    // whole debt for positions that are already underwater.
+    pub fn deleverage_needed(
+        &self,
+        target_ratio: u16,
+        collateral_price: &CollateralPrice,
+    ) -> Result<u64> {
+        require!(
+            target_ratio as u64 > HEALTH_RATIO_SCALE,
+            ErrorCode::InvalidTargetRatio
+        );
+        let excess = (self.debt as u128)
+            .checked_mul(target_ratio as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .saturating_sub(self.collateral_value as u128 * HEALTH_RATIO_SCALE as u128);
+        let value = excess
+            .div_ceil((target_ratio as u64 - HEALTH_RATIO_SCALE) as u128)
+            .min(self.debt as u128);
+
+        collateral_price.tokens_for(value)
+    }
+
+    // Splits deleverage proceeds into the stablecoins that repay the debt and the surplus
+    // beyond it
+    pub fn repayment_from_proceeds(&self, received: u64) -> (u64, u64) {
+        let repaid = received.min(self.debt);
+        (repaid, received - repaid)
+    }
+
+    // Stablecoins to repay so that value >= remaining debt * target / 10_000, rounded up
+    pub fn repayment_needed(&self, target_ratio: u16) -> Result<u64> {
+        require!(target_ratio > 0, ErrorCode::InvalidThreshold);
+        let supported_debt = (self.collateral_value as u128)
+            .checked_mul(HEALTH_RATIO_SCALE as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / target_ratio as u128;
+
+        Ok((self.debt as u128).saturating_sub(supported_debt) as u64)
+    }
 }
 
-// Calculates amount needed to restore health ratio
-fn calculate_protection_amount(current_ratio: u16, target_ratio: u8) -> Result<u64> {
-    // Mock implementation - in reality would be based on position size and prices
-    // For demo, assume 1 SOL (~$100) per 10% health ratio improvement needed
-    let ratio_diff = target_ratio as u16 - current_ratio;
-    let sol_needed = (ratio_diff as u64 + 9) / 10; // Ceiling division
-    let lamports_needed = sol_needed * 1_000_000_000; // SOL to lamports
-    
-    Ok(lamports_needed)
+// Oracle price of one whole collateral token. Smart vault shares are priced at their
+// haircut value, the same value deposits of them are accepted at.
+pub(crate) struct CollateralPrice {
+    pub price: u64, // Scaled by PRICE_SCALE
+    pub decimals: u8,
+    pub is_share_collateral: bool,
 }
 
-// Calculates new health ratio after protection action
-fn calculate_new_health_ratio(current_ratio: u16, _protection_amount: u64) -> Result<u16> {
-    // Mock implementation - in reality would recalculate based on new position value
-    // For demo, assume each SOL adds 10% to health ratio
-    let sol_amount = _protection_amount / 1_000_000_000;
-    let new_ratio = current_ratio + (sol_amount * 10) as u16;
-    
-    Ok(new_ratio)
+impl CollateralPrice {
+    pub fn load(
+        stablecoin_config: &StablecoinConfig,
+        collateral_mint: &Account<token::Mint>,
+        collateral_price_feed: &PriceFeed,
+        share_collateral_vault: Option<&Account<SmartVault>>,
+    ) -> Result<Self> {
+        if let CollateralType::SmartVaultShares { smart_vault } = stablecoin_config.collateral_type
+        {
+            let vault = share_collateral_vault.ok_or(ErrorCode::MissingShareCollateralVault)?;
+            require_keys_eq!(vault.key(), smart_vault, ErrorCode::InvalidShareCollateral);
+            require_keys_eq!(
+                collateral_mint.key(),
+                vault.share_mint,
+                ErrorCode::InvalidShareCollateral
+            );
+
+            return Ok(Self {
+                price: vault.share_collateral_price(collateral_mint, collateral_price_feed)?,
+                decimals: collateral_mint.decimals,
+                is_share_collateral: true,
+            });
+        }
+
+        require_keys_eq!(
+            collateral_price_feed.mint,
+            collateral_mint.key(),
+            ErrorCode::InvalidPriceFeed
+        );
+        Ok(Self {
+            price: collateral_price_feed.current_price()?,
+            decimals: collateral_mint.decimals,
+            is_share_collateral: false,
+        })
+    }
+
+    // Value of `amount` base units, rounded down
+    pub fn value_of(&self, amount: u64) -> Result<u64> {
+        let value = (amount as u128)
+            .checked_mul(self.price as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / 10u128.pow(self.decimals as u32);
+
+        u64::try_from(value).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+
+    // Oracle value of `amount` moved by an action, in stablecoins for auto-repay and in
+    // collateral tokens otherwise. Stablecoins are pegged to $1.
+    pub fn value_moved(&self, action_type: ProtectionActionType, amount: u64) -> Result<u64> {
+        match action_type {
+            ProtectionActionType::AutoRepay => Ok(amount),
+            _ => self.value_of(amount),
+        }
+    }
+
+    // Amount of what `action_type` moves that is worth at most `value`
+    pub fn amount_for_value(&self, action_type: ProtectionActionType, value: u64) -> Result<u64> {
+        if action_type == ProtectionActionType::AutoRepay {
+            return Ok(value);
+        }
+        require!(self.price > 0, ErrorCode::InvalidPriceFeed);
+        let tokens = (value as u128)
+            .checked_mul(10u128.pow(self.decimals as u32))
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / self.price as u128;
+
+        Ok(tokens.min(u64::MAX as u128) as u64)
+    }
+
+    // Base units worth `value`, rounded up
+    pub fn tokens_for(&self, value: u128) -> Result<u64> {
+        require!(self.price > 0, ErrorCode::InvalidPriceFeed);
+        let tokens = value
+            .checked_mul(10u128.pow(self.decimals as u32))
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .div_ceil(self.price as u128);
+
+        u64::try_from(tokens).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
 }
 
 #[error_code]
 pub enum ErrorCode {
     #[msg("Health ratio threshold must be greater than 100%")]
     InvalidThreshold,
-    #[msg("Invalid protection mode")]
-    InvalidProtectionMode,
+    #[msg("Target health ratio must be above the trigger ratio")]
+    InvalidTargetRatio,
     #[msg("Cooldown period is still active")]
     CooldownPeriodActive,
-    #[msg("Protection not needed, health ratio above threshold")]
+    #[msg("Protection budget and window must be positive")]
+    InvalidBudget,
+    #[msg("Protection budget for the current window is exhausted")]
+    ProtectionBudgetExhausted,
+    #[msg("Protection is not active")]
+    ProtectionInactive,
+    #[msg("Protection not needed, health ratio above trigger")]
     ProtectionNotNeeded,
     #[msg("Insufficient funds for protection action")]
     InsufficientFunds,
-    #[msg("Invalid protection action type")]
-    InvalidActionType,
     #[msg("Action type not allowed by current protection mode")]
     ActionTypeNotAllowed,
-} 
\ No newline at end of file
+    #[msg("Auto-repay requires the stablecoin mint and repay source accounts")]
+    MissingRepayAccounts,
+    #[msg("Unwinding requires the smart vault, share mint, share account and vault token account")]
+    MissingSmartVaultAccounts,
+    #[msg("Smart vault accounts do not match")]
+    InvalidSmartVaultAccounts,
+    #[msg("Deleveraging requires the stablecoin mint, swap adapter, swap program and proceeds account")]
+    MissingDeleverageAccounts,
+    #[msg("Deleverage proceeds beyond the debt need the owner's stablecoin account")]
+    MissingSurplusAccount,
+    #[msg("Swap program is not registered by the swap adapter")]
+    UnregisteredSwapProgram,
+    #[msg("Protection account is not approved as delegate for the protection amount")]
+    InsufficientDelegation,
+    #[msg("Accounts passed to close protection do not belong to it")]
+    InvalidCloseAccounts,
+    #[msg("Protection account still has action records, pass them in to close them")]
+    ActionRecordsOpen,
+    #[msg("Stablecoins backed by smart vault shares need the share collateral vault")]
+    MissingShareCollateralVault,
+    #[msg("Share collateral vault or mint does not match the stablecoin's collateral")]
+    InvalidShareCollateral,
+    #[msg("Price feed does not match the collateral")]
+    InvalidPriceFeed,
+    #[msg("Only the owner or a registered keeper can execute protection")]
+    UnregisteredKeeper,
+    #[msg("Keeper is not active")]
+    KeeperInactive,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::PRICE_SCALE;
+
+    // $2,000 per whole token with 9 decimals
+    fn collateral_price() -> CollateralPrice {
+        CollateralPrice {
+            price: 2_000 * PRICE_SCALE,
+            decimals: 9,
+            is_share_collateral: false,
+        }
+    }
+
+    fn position(collateral_value: u64, debt: u64) -> PositionHealth {
+        PositionHealth {
+            collateral_value,
+            debt,
+        }
+    }
+
+    // $1,000 of budget per day
+    fn protection_account() -> ProtectionAccount {
+        ProtectionAccount {
+            owner: Pubkey::new_unique(),
+            stablecoin_mint: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            config: ProtectionConfig {
+                trigger_ratio_bps: 12_000,
+                target_ratio_bps: 15_000,
+                protection_mode: ProtectionMode::All,
+                max_protection_amount: u64::MAX,
+                notification_only: false,
+                cooldown_period: 0,
+                auto_collateral_source: Pubkey::new_unique(),
+                budget_amount: 1_000 * PRICE_SCALE,
+                budget_window: 86_400,
+            },
+            is_active: true,
+            last_protection_time: 0,
+            total_protection_actions: 0,
+            total_protected_amount: 0,
+            last_health_ratio: 0,
+            next_action_id: 0,
+            open_action_records: 0,
+            budget_window_start: 0,
+            budget_spent: 0,
+            custodied_collateral: 0,
+            bump: 255,
+            version: PROTECTION_LAYOUT_VERSION,
+        }
+    }
+
+    #[test]
+    fn health_ratio_is_collateral_value_over_debt_in_bps() {
+        assert_eq!(position(150, 100).health_ratio().unwrap(), 15_000);
+        assert_eq!(position(100, 100).health_ratio().unwrap(), 10_000);
+        assert_eq!(position(0, 100).health_ratio().unwrap(), 0);
+    }
+
+    #[test]
+    fn health_ratio_rounds_down() {
+        assert_eq!(position(1, 3).health_ratio().unwrap(), 3_333);
+        assert_eq!(position(2, 3).health_ratio().unwrap(), 6_666);
+    }
+
+    #[test]
+    fn health_ratio_saturates_for_debt_free_and_overcollateralized_positions() {
+        assert_eq!(position(0, 0).health_ratio().unwrap(), u16::MAX);
+        assert_eq!(position(1_000, 0).health_ratio().unwrap(), u16::MAX);
+        assert_eq!(position(7, 1).health_ratio().unwrap(), u16::MAX);
+        assert_eq!(position(u64::MAX, 1).health_ratio().unwrap(), u16::MAX);
+    }
+
+    #[test]
+    fn collateralization_includes_the_ratio_boundary() {
+        assert!(position(150, 100).is_collateralized(15_000));
+        assert!(!position(149, 100).is_collateralized(15_000));
+        assert!(position(0, 0).is_collateralized(15_000));
+        assert!(!position(0, 1).is_collateralized(15_000));
+        assert!(position(u64::MAX, u64::MAX).is_collateralized(10_000));
+    }
+
+    #[test]
+    fn collateral_is_valued_at_the_oracle_price_rounding_down() {
+        let price = collateral_price();
+        assert_eq!(price.value_of(1_000_000_000).unwrap(), 2_000 * PRICE_SCALE);
+        assert_eq!(price.value_of(1).unwrap(), 2);
+        assert_eq!(price.value_of(499).unwrap(), 998);
+        assert_eq!(price.value_of(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn collateral_needed_restores_the_target_ratio() {
+        let price = collateral_price();
+        let position = position(1_200_000_000, 1_000_000_000);
+
+        // $300 short of 150%, at $2,000 per token
+        let needed = position.collateral_needed(15_000, &price).unwrap();
+        assert_eq!(needed, 150_000_000);
+        let after = position
+            .after(ProtectionActionType::AddCollateral, needed, &price)
+            .unwrap();
+        assert_eq!(after.health_ratio().unwrap(), 15_000);
+    }
+
+    #[test]
+    fn collateral_needed_rounds_up() {
+        let price = collateral_price();
+
+        // Three units of value short, each base unit is worth two
+        assert_eq!(position(0, 2).collateral_needed(15_000, &price).unwrap(), 2);
+
+        let position = position(1_234_567, 1_000_003);
+        let needed = position.collateral_needed(16_500, &price).unwrap();
+        let after = position
+            .after(ProtectionActionType::AddCollateral, needed, &price)
+            .unwrap();
+        assert!(after.health_ratio().unwrap() >= 16_500);
+    }
+
+    #[test]
+    fn nothing_is_needed_at_or_above_the_target_or_without_debt() {
+        let price = collateral_price();
+        for position in [position(1_500, 1_000), position(1_000, 0), position(0, 0)] {
+            assert_eq!(position.collateral_needed(15_000, &price).unwrap(), 0);
+            assert_eq!(position.repayment_needed(15_000).unwrap(), 0);
+            assert_eq!(position.deleverage_needed(15_000, &price).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn repayment_needed_restores_the_target_ratio() {
+        let position = position(1_200_000_000, 1_000_000_000);
+        let needed = position.repayment_needed(15_000).unwrap();
+        assert_eq!(needed, 200_000_000);
+
+        let after = position
+            .after(ProtectionActionType::AutoRepay, needed, &collateral_price())
+            .unwrap();
+        assert_eq!(after.health_ratio().unwrap(), 15_000);
+    }
+
+    #[test]
+    fn repayment_needed_rounds_up() {
+        // 10 of value supports 6.67 of debt at 150%, so 1 of the 7 is repaid
+        let position = position(10, 7);
+        let needed = position.repayment_needed(15_000).unwrap();
+        assert_eq!(needed, 1);
+
+        let after = position
+            .after(ProtectionActionType::AutoRepay, needed, &collateral_price())
+            .unwrap();
+        assert!(after.health_ratio().unwrap() >= 15_000);
+        assert!(position.repayment_needed(0).is_err());
+    }
+
+    #[test]
+    fn deleverage_needed_restores_the_target_ratio() {
+        let price = collateral_price();
+        let position = position(1_200_000_000, 1_000_000_000);
+
+        // Selling $600 leaves $600 of collateral against $400 of debt
+        let needed = position.deleverage_needed(15_000, &price).unwrap();
+        assert_eq!(needed, 300_000_000);
+        let after = position
+            .after(ProtectionActionType::Deleverage, needed, &price)
+            .unwrap();
+        assert_eq!(after.health_ratio().unwrap(), 15_000);
+    }
+
+    #[test]
+    fn deleverage_needed_is_capped_at_the_debt_for_underwater_positions() {
+        let price = collateral_price();
+        let position = position(900_000_000, 1_000_000_000);
+
+        assert_eq!(
+            position.deleverage_needed(15_000, &price).unwrap(),
+            500_000_000
+        );
+    }
+
+    #[test]
+    fn deleverage_proceeds_beyond_the_debt_are_surplus() {
+        let price = collateral_price();
+        let position = position(900_000_000, 1_000_000_000);
+
+        // Selling the capped amount fetches $1,005 when the swap beats the oracle
+        let sold = position.deleverage_needed(15_000, &price).unwrap();
+        assert_eq!(price.value_of(sold).unwrap(), 1_000_000_000);
+        assert_eq!(
+            position.repayment_from_proceeds(1_005_000_000),
+            (1_000_000_000, 5_000_000)
+        );
+
+        // Proceeds within the debt are all repaid
+        assert_eq!(
+            position.repayment_from_proceeds(990_000_000),
+            (990_000_000, 0)
+        );
+        assert_eq!(
+            position.repayment_from_proceeds(1_000_000_000),
+            (1_000_000_000, 0)
+        );
+    }
+
+    #[test]
+    fn deleverage_needs_a_target_above_one_hundred_percent() {
+        let price = collateral_price();
+        assert!(position(1_200, 1_000)
+            .deleverage_needed(10_000, &price)
+            .is_err());
+        assert!(position(1_200, 1_000)
+            .deleverage_needed(9_000, &price)
+            .is_err());
+    }
+
+    #[test]
+    fn budget_is_spent_within_a_window() {
+        let mut account = protection_account();
+        account.budget_window_start = 1_000;
+        account.roll_budget_window(1_000);
+
+        account
+            .record_execution(1_000, 100, 400 * PRICE_SCALE, 12_500)
+            .unwrap();
+        assert_eq!(account.remaining_budget(2_000), 600 * PRICE_SCALE);
+        account.roll_budget_window(2_000);
+        account
+            .record_execution(2_000, 100, 700 * PRICE_SCALE, 13_000)
+            .unwrap();
+        assert_eq!(account.remaining_budget(3_000), 0);
+        assert_eq!(account.budget_window_start, 1_000);
+        assert_eq!(account.total_protection_actions, 2);
+        assert_eq!(account.total_protected_amount, 200);
+    }
+
+    #[test]
+    fn budget_window_restarts_once_fully_elapsed() {
+        let mut account = protection_account();
+        account.budget_window_start = 1_000;
+        account.budget_spent = 1_000 * PRICE_SCALE;
+
+        // One second short of the window
+        assert_eq!(account.remaining_budget(87_399), 0);
+        account.roll_budget_window(87_399);
+        assert_eq!(account.budget_window_start, 1_000);
+        assert_eq!(account.budget_spent, 1_000 * PRICE_SCALE);
+
+        // Counted as restarted before it is rolled
+        assert_eq!(account.remaining_budget(87_400), 1_000 * PRICE_SCALE);
+        account.roll_budget_window(87_400);
+        assert_eq!(account.budget_window_start, 87_400);
+        assert_eq!(account.budget_spent, 0);
+    }
+
+    #[test]
+    fn budget_caps_amounts_by_oracle_value() {
+        let price = collateral_price();
+        let budget = 1_000 * PRICE_SCALE;
+
+        // Half a token of collateral, or the budget itself in stablecoins
+        assert_eq!(
+            price
+                .amount_for_value(ProtectionActionType::AddCollateral, budget)
+                .unwrap(),
+            500_000_000
+        );
+        assert_eq!(
+            price
+                .amount_for_value(ProtectionActionType::Deleverage, budget)
+                .unwrap(),
+            500_000_000
+        );
+        assert_eq!(
+            price
+                .amount_for_value(ProtectionActionType::AutoRepay, budget)
+                .unwrap(),
+            budget
+        );
+
+        // Rounded down so the amount never moves more than the budget
+        assert_eq!(
+            price
+                .amount_for_value(ProtectionActionType::AddCollateral, 3)
+                .unwrap(),
+            1
+        );
+        assert!(
+            price
+                .value_moved(ProtectionActionType::AddCollateral, 1)
+                .unwrap()
+                <= 3
+        );
+        assert_eq!(
+            price
+                .value_moved(ProtectionActionType::AutoRepay, budget)
+                .unwrap(),
+            budget
+        );
+    }
+
+    #[test]
+    fn exhausted_budget_skips_protection() {
+        let price = collateral_price();
+        let position = position(1_000_000_000, 1_000_000_000);
+        let mut account = protection_account();
+        account.budget_window_start = 1_000;
+        account.budget_spent = 1_000 * PRICE_SCALE;
+
+        let plan = account
+            .plan_protection(
+                &position,
+                10_000,
+                ProtectionActionType::AutoRepay,
+                &price,
+                2_000,
+            )
+            .unwrap();
+        assert_eq!(plan, ProtectionPlan::Skip(ProtectionSkip::BudgetExhausted));
+
+        // A partly spent budget limits the amount instead
+        account.budget_spent = 900 * PRICE_SCALE;
+        let plan = account
+            .plan_protection(
+                &position,
+                10_000,
+                ProtectionActionType::AutoRepay,
+                &price,
+                2_000,
+            )
+            .unwrap();
+        assert_eq!(plan, ProtectionPlan::Execute(100 * PRICE_SCALE));
+    }
+
+    #[test]
+    fn notifications_start_the_cooldown() {
+        let price = collateral_price();
+        let position = position(1_000_000_000, 1_000_000_000);
+        let mut account = protection_account();
+        account.config.notification_only = true;
+        account.config.cooldown_period = 3_600;
+
+        let plan = account
+            .plan_protection(
+                &position,
+                10_000,
+                ProtectionActionType::AutoRepay,
+                &price,
+                10_000,
+            )
+            .unwrap();
+        assert_eq!(plan, ProtectionPlan::NotifyOnly);
+        account.record_notification(10_000, 10_000);
+
+        let plan = account
+            .plan_protection(
+                &position,
+                10_000,
+                ProtectionActionType::AutoRepay,
+                &price,
+                13_599,
+            )
+            .unwrap();
+        assert_eq!(plan, ProtectionPlan::Skip(ProtectionSkip::CooldownActive));
+        let plan = account
+            .plan_protection(
+                &position,
+                10_000,
+                ProtectionActionType::AutoRepay,
+                &price,
+                13_600,
+            )
+            .unwrap();
+        assert_eq!(plan, ProtectionPlan::NotifyOnly);
+    }
+
+    #[test]
+    fn delegated_sources_need_the_balance_and_the_approval() {
+        let protection_account = Pubkey::new_unique();
+        let source = |amount, delegate, delegated_amount| spl_token::state::Account {
+            amount,
+            delegate,
+            delegated_amount,
+            ..Default::default()
+        };
+
+        assert!(check_delegated_source(
+            &source(500, COption::Some(protection_account), 500),
+            protection_account,
+            500
+        )
+        .is_ok());
+        assert_eq!(
+            check_delegated_source(
+                &source(499, COption::Some(protection_account), 500),
+                protection_account,
+                500
+            )
+            .unwrap_err(),
+            ErrorCode::InsufficientFunds.into()
+        );
+        assert_eq!(
+            check_delegated_source(
+                &source(500, COption::Some(protection_account), 499),
+                protection_account,
+                500
+            )
+            .unwrap_err(),
+            ErrorCode::InsufficientDelegation.into()
+        );
+        assert_eq!(
+            check_delegated_source(
+                &source(500, COption::Some(Pubkey::new_unique()), 500),
+                protection_account,
+                500
+            )
+            .unwrap_err(),
+            ErrorCode::InsufficientDelegation.into()
+        );
+        assert_eq!(
+            check_delegated_source(&source(500, COption::None, 0), protection_account, 500)
+                .unwrap_err(),
+            ErrorCode::InsufficientDelegation.into()
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::global_config::MAX_BPS;
+use crate::oracle::PriceFeed;
+use crate::smart_vaults::{mul_div, SmartVault, StrategyAdapter};
+use crate::swap_adapter::{swap_exact_in, SwapAdapter};
+
+// Strategy swaps must return at least this close to the oracle value of what they sell
+pub const STRATEGY_SWAP_MAX_SLIPPAGE_BPS: u16 = 100;
+
+// Value of a vault in the oracle's quote unit (USD scaled by PRICE_SCALE)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct VaultValuation {
+    pub idle_value: u64,     // Free idle balance in the vault token account
+    pub strategy_value: u64, // Every strategy position, at its own asset's price
+    pub total_value: u64,
+}
+
+// Swap program specific accounts are passed as remaining accounts
+#[derive(Accounts)]
+pub struct SwapStrategy<'info> {
+    pub curator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        constraint = smart_vault.active
+    )]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    #[account(
+        mut,
+        seeds = [b"strategy-adapter", smart_vault.key().as_ref(), &[strategy_adapter.strategy_id]],
+        bump = strategy_adapter.bump,
+        has_one = smart_vault,
+        has_one = position_token_account,
+        constraint = !strategy_adapter.holds_vault_underlying(&smart_vault) @ ErrorCode::StrategyHoldsVaultUnderlying
+    )]
+    pub strategy_adapter: Box<Account<'info, StrategyAdapter>>,
+
+    #[account(mut)]
+    pub position_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(address = smart_vault.collateral_mint)]
+    pub collateral_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"price-feed", collateral_mint.key().as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+
+    #[account(address = strategy_adapter.asset_mint)]
+    pub asset_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"price-feed", asset_mint.key().as_ref()],
+        bump = asset_price_feed.bump
+    )]
+    pub asset_price_feed: Box<Account<'info, PriceFeed>>,
+
+    #[account(
+        seeds = [b"swap-adapter", swap_program.key().as_ref()],
+        bump = swap_adapter.bump
+    )]
+    pub swap_adapter: Account<'info, SwapAdapter>,
+
+    /// CHECK: Registered through its swap adapter account
+    #[account(executable)]
+    pub swap_program: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Every allocated strategy is passed as a remaining account quadruple of
+// (adapter, position token account, asset mint, price feed), in allocation order
+#[derive(Accounts)]
+pub struct GetVaultValue<'info> {
+    #[account(has_one = collateral_mint)]
+    pub smart_vault: Box<Account<'info, SmartVault>>,
+
+    #[account(constraint = vault_token_account.key() == smart_vault.vault_token_account)]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub collateral_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        seeds = [b"price-feed", collateral_mint.key().as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+}
+
+// Implementation of functions
+
+// Swaps idle vault funds into a strategy held in another asset. `min_amount_out` can only
+// tighten the oracle bound, never loosen it.
+pub fn swap_into_strategy<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SwapStrategy<'info>>,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    require!(
+        amount_in <= smart_vault.free_idle_balance(&ctx.accounts.vault_token_account),
+        ErrorCode::InsufficientIdleBalance
+    );
+    let min_amount_out = min_amount_out.max(oracle_min_amount_out(
+        amount_in,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.collateral_mint.decimals,
+        &ctx.accounts.asset_price_feed,
+        ctx.accounts.asset_mint.decimals,
+    )?);
+
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let (spent, received) = swap_exact_in(
+        &ctx.accounts.swap_program,
+        &smart_vault.to_account_info(),
+        &mut ctx.accounts.vault_token_account,
+        &mut ctx.accounts.position_token_account,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        amount_in,
+        min_amount_out,
+        &[&seeds[..]],
+    )?;
+
+    // Principal is tracked at cost in the vault's underlying
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    strategy_adapter.deployed_amount = strategy_adapter.deployed_amount.checked_add(spent).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.add_illiquid(spent)?;
+    smart_vault.total_allocated = smart_vault.total_allocated.checked_add(spent).ok_or(ErrorCode::ArithmeticOverflow)?;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    msg!("Swapped {} into strategy {} for {}", spent, strategy_adapter.strategy_id, received);
+    Ok(())
+}
+
+// Swaps `amount_in` of a strategy's asset back into the vault's underlying. The difference
+// from the cost of the part sold is realized as yield or loss, as in `emergency_withdraw_all`.
+pub fn swap_out_of_strategy<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SwapStrategy<'info>>,
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Result<()> {
+    let strategy_adapter = &ctx.accounts.strategy_adapter;
+    let position_value = strategy_adapter.current_value(&ctx.accounts.position_token_account);
+    require!(
+        amount_in > 0 && amount_in <= ctx.accounts.position_token_account.amount,
+        ErrorCode::InvalidSwapAmount
+    );
+    let min_amount_out = min_amount_out.max(oracle_min_amount_out(
+        amount_in,
+        &ctx.accounts.asset_price_feed,
+        ctx.accounts.asset_mint.decimals,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.collateral_mint.decimals,
+    )?);
+
+    let smart_vault = &ctx.accounts.smart_vault;
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let (spent, received) = swap_exact_in(
+        &ctx.accounts.swap_program,
+        &smart_vault.to_account_info(),
+        &mut ctx.accounts.position_token_account,
+        &mut ctx.accounts.vault_token_account,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        amount_in,
+        min_amount_out,
+        &[&seeds[..]],
+    )?;
+
+    // Cost of the part actually sold
+    let cost = if spent == position_value {
+        strategy_adapter.deployed_amount
+    } else {
+        mul_div(strategy_adapter.deployed_amount, spent, position_value)?
+    };
+
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    strategy_adapter.deployed_amount -= cost;
+
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.remove_illiquid(cost);
+    smart_vault.total_allocated = smart_vault.total_allocated.saturating_sub(cost);
+    if received >= cost {
+        let gain = received - cost;
+        smart_vault.total_yield_earned = smart_vault.total_yield_earned.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        if smart_vault.auto_compound {
+            smart_vault.total_deposited = smart_vault.total_deposited.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        } else {
+            smart_vault.claimable_yield = smart_vault.claimable_yield.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+    } else {
+        smart_vault.total_deposited = smart_vault.total_deposited.saturating_sub(cost - received);
+    }
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    msg!("Swapped {} out of strategy {} for {}", spent, strategy_adapter.strategy_id, received);
+    Ok(())
+}
+
+pub fn get_vault_value<'info>(ctx: Context<'_, '_, 'info, 'info, GetVaultValue<'info>>) -> Result<VaultValuation> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let remaining_accounts = ctx.remaining_accounts;
+    require!(
+        remaining_accounts.len() == smart_vault.strategies.len() * 4,
+        ErrorCode::InvalidStrategyAccounts
+    );
+
+    let idle_value = ctx.accounts.collateral_price_feed.value_of(
+        smart_vault.free_idle_balance(&ctx.accounts.vault_token_account),
+        ctx.accounts.collateral_mint.decimals,
+    )?;
+
+    let mut strategy_value: u64 = 0;
+    for (allocation, accounts) in smart_vault.strategies.iter().zip(remaining_accounts.chunks(4)) {
+        let adapter = Account::<StrategyAdapter>::try_from(&accounts[0])?;
+        let position = Account::<TokenAccount>::try_from(&accounts[1])?;
+        let asset_mint = Account::<Mint>::try_from(&accounts[2])?;
+        let price_feed = Account::<PriceFeed>::try_from(&accounts[3])?;
+
+        require!(adapter.smart_vault == smart_vault.key(), ErrorCode::InvalidStrategyAccounts);
+        require!(adapter.strategy_id == allocation.strategy_id, ErrorCode::InvalidStrategyAccounts);
+        require!(position.key() == adapter.position_token_account, ErrorCode::InvalidStrategyAccounts);
+        require!(asset_mint.key() == adapter.asset_mint, ErrorCode::InvalidStrategyAccounts);
+        require!(price_feed.mint == adapter.asset_mint, ErrorCode::InvalidStrategyAccounts);
+
+        let value = price_feed.value_of(adapter.current_value(&position), asset_mint.decimals)?;
+        strategy_value = strategy_value.checked_add(value).ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    Ok(VaultValuation {
+        idle_value,
+        strategy_value,
+        total_value: idle_value.checked_add(strategy_value).ok_or(ErrorCode::ArithmeticOverflow)?,
+    })
+}
+
+// Helper functions
+
+// Least amount of the output token a swap of `amount_in` may return: its oracle value in
+// the output token, less the maximum slippage
+fn oracle_min_amount_out(
+    amount_in: u64,
+    input_price_feed: &PriceFeed,
+    input_decimals: u8,
+    output_price_feed: &PriceFeed,
+    output_decimals: u8,
+) -> Result<u64> {
+    let value = input_price_feed.value_of(amount_in, input_decimals)?;
+    let amount_out = (value as u128)
+        .checked_mul(10u128.pow(output_decimals as u32))
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        / output_price_feed.current_price()? as u128;
+    let amount_out = u64::try_from(amount_out).map_err(|_| ErrorCode::ArithmeticOverflow)?;
+
+    mul_div(amount_out, (MAX_BPS - STRATEGY_SWAP_MAX_SLIPPAGE_BPS) as u64, MAX_BPS as u64)
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Strategy holds the vault's underlying, use rebalance_strategies instead")]
+    StrategyHoldsVaultUnderlying,
+    #[msg("Not enough free idle balance in the vault")]
+    InsufficientIdleBalance,
+    #[msg("Swap amount must be positive and within the position")]
+    InvalidSwapAmount,
+    #[msg("Strategy accounts do not match the vault's allocations")]
+    InvalidStrategyAccounts,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
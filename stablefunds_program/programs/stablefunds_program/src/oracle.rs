@@ -0,0 +1,114 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+use crate::global_config::GlobalConfig;
+
+// Prices are quoted in USD per whole token with 6 decimals, matching the stablecoin mints
+pub const PRICE_SCALE: u64 = 1_000_000;
+// Prices older than this are rejected
+pub const MAX_PRICE_AGE: i64 = 10 * 60;
+
+// On-chain price pushed by the NCN oracle bridge for a single mint
+#[account]
+pub struct PriceFeed {
+    pub mint: Pubkey,
+    pub authority: Pubkey, // Allowed to push prices, typically the oracle bridge
+    pub price: u64,        // USD per whole token, scaled by PRICE_SCALE
+    pub last_update_time: i64,
+    pub bump: u8,
+}
+
+impl PriceFeed {
+    pub const SPACE: usize = 8 + 32 + 32 + 8 + 8 + 1;
+
+    pub fn current_price(&self) -> Result<u64> {
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(self.price > 0, ErrorCode::InvalidPrice);
+        require!(current_time - self.last_update_time <= MAX_PRICE_AGE, ErrorCode::StalePrice);
+
+        Ok(self.price)
+    }
+
+    // USD value (scaled by PRICE_SCALE) of `amount` base units of a mint with `decimals`
+    pub fn value_of(&self, amount: u64, decimals: u8) -> Result<u64> {
+        let value = (amount as u128)
+            .checked_mul(self.current_price()? as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / 10u128.pow(decimals as u32);
+
+        u64::try_from(value).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializePriceFeed<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = PriceFeed::SPACE,
+        seeds = [b"price-feed", mint.key().as_ref()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePriceFeed<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"price-feed", price_feed.mint.as_ref()],
+        bump = price_feed.bump,
+        has_one = authority
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+}
+
+// Implementation of functions
+
+pub fn initialize_price_feed(ctx: Context<InitializePriceFeed>, price_authority: Pubkey) -> Result<()> {
+    let price_feed = &mut ctx.accounts.price_feed;
+
+    price_feed.mint = ctx.accounts.mint.key();
+    price_feed.authority = price_authority;
+    price_feed.price = 0;
+    price_feed.last_update_time = 0;
+    price_feed.bump = ctx.bumps.price_feed;
+
+    Ok(())
+}
+
+pub fn update_price_feed(ctx: Context<UpdatePriceFeed>, price: u64) -> Result<()> {
+    require!(price > 0, ErrorCode::InvalidPrice);
+
+    let price_feed = &mut ctx.accounts.price_feed;
+    price_feed.price = price;
+    price_feed.last_update_time = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Oracle price must be positive")]
+    InvalidPrice,
+    #[msg("Oracle price is stale")]
+    StalePrice,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,589 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::token::{Mint, Token, TokenAccount};
+
+use crate::keeper_registry::{Keeper, KeeperRegistry};
+use crate::liquidation_protection::{
+    burn_as_delegate, check_delegated_source, executed_by_keeper, new_action_record, reward_executing_keeper,
+    transfer_as_delegate, CollateralPrice, PositionHealth, ProtectionAccount, ProtectionActionRecord, ProtectionActionType,
+    ProtectionPlan, ProtectionSkip,
+};
+use crate::oracle::PriceFeed;
+use crate::smart_vaults::SmartVault;
+use crate::{StablecoinConfig, UserCollateral, UserStablecoin};
+
+// Results are returned as return data, which is limited to 1024 bytes
+pub const MAX_BATCH_SIZE: usize = 20;
+
+const ACCOUNTS_PER_POSITION: usize = 5;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatchProtectionStatus {
+    Executed,
+    NotificationOnly,  // Recorded without moving funds
+    NotNeeded,         // At or above the trigger ratio
+    Inactive,
+    ActionNotAllowed,  // Not allowed by the protection mode
+    CooldownActive,
+    BudgetExhausted,
+    InsufficientFunds, // Source balance or delegation below the protection amount
+}
+
+impl From<ProtectionSkip> for BatchProtectionStatus {
+    fn from(skip: ProtectionSkip) -> Self {
+        match skip {
+            ProtectionSkip::Inactive => BatchProtectionStatus::Inactive,
+            ProtectionSkip::NotNeeded => BatchProtectionStatus::NotNeeded,
+            ProtectionSkip::ActionNotAllowed => BatchProtectionStatus::ActionNotAllowed,
+            ProtectionSkip::CooldownActive => BatchProtectionStatus::CooldownActive,
+            ProtectionSkip::BudgetExhausted => BatchProtectionStatus::BudgetExhausted,
+        }
+    }
+}
+
+// Outcome of a single position in a batch
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct BatchProtectionResult {
+    pub protection_account: Pubkey,
+    pub status: BatchProtectionStatus,
+    pub amount: u64,
+    pub health_ratio_before: u16,
+    pub health_ratio_after: u16,
+}
+
+// Positions share a stablecoin and a collateral mint. Each one is passed as a writable
+// remaining account group of (protection account, user collateral, user stablecoin, source
+// token account, action record), where the source is the auto collateral source for
+// AddCollateral or the owner's delegated stablecoin account for AutoRepay.
+#[derive(Accounts)]
+pub struct ExecuteProtectionBatch<'info> {
+    // The owner of every position or a registered keeper
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper", authority.key().as_ref()],
+        bump = keeper.bump,
+        constraint = keeper.active @ ErrorCode::KeeperInactive
+    )]
+    pub keeper: Option<Box<Account<'info, Keeper>>>,
+
+    #[account(
+        mut,
+        seeds = [b"keeper-registry"],
+        bump = keeper_registry.bump
+    )]
+    pub keeper_registry: Option<Box<Account<'info, KeeperRegistry>>>,
+
+    #[account(mut)]
+    pub stablecoin_config: Box<Account<'info, StablecoinConfig>>,
+
+    /// CHECK: This is a PDA that holds the stablecoin's collateral
+    #[account(
+        seeds = [b"stablecoin-vault", stablecoin_config.key().as_ref()],
+        bump
+    )]
+    pub stablecoin_vault: UncheckedAccount<'info>,
+
+    #[account(mut, address = stablecoin_config.mint)]
+    pub stablecoin_mint: Box<Account<'info, Mint>>,
+
+    pub collateral_mint: Box<Account<'info, Mint>>,
+
+    // Feed of the collateral, or of the smart vault's underlying for share collateral
+    #[account(
+        seeds = [b"price-feed", collateral_price_feed.mint.as_ref()],
+        bump = collateral_price_feed.bump
+    )]
+    pub collateral_price_feed: Box<Account<'info, PriceFeed>>,
+
+    // Required when the stablecoin is backed by smart vault shares
+    pub share_collateral_vault: Option<Box<Account<'info, SmartVault>>>,
+
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == collateral_mint.key(),
+        constraint = destination_token_account.owner == stablecoin_vault.key()
+    )]
+    pub destination_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// Implementation of functions
+
+// Protects every position it can, skipping the ones that need no action or cannot take it.
+// Invalid accounts still fail the whole batch.
+pub fn execute_protection_batch<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteProtectionBatch<'info>>,
+    action_type: ProtectionActionType,
+) -> Result<Vec<BatchProtectionResult>> {
+    require!(
+        matches!(action_type, ProtectionActionType::AutoRepay | ProtectionActionType::AddCollateral),
+        ErrorCode::BatchActionUnsupported
+    );
+
+    let remaining_accounts = ctx.remaining_accounts;
+    require!(
+        !remaining_accounts.is_empty() && remaining_accounts.len().is_multiple_of(ACCOUNTS_PER_POSITION),
+        ErrorCode::InvalidBatchAccounts
+    );
+    require!(
+        remaining_accounts.len() / ACCOUNTS_PER_POSITION <= MAX_BATCH_SIZE,
+        ErrorCode::BatchTooLarge
+    );
+
+    let current_time = Clock::get()?.unix_timestamp;
+    let collateral_price = CollateralPrice::load(
+        &ctx.accounts.stablecoin_config,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.collateral_price_feed,
+        ctx.accounts.share_collateral_vault.as_deref(),
+    )?;
+
+    remaining_accounts
+        .chunks(ACCOUNTS_PER_POSITION)
+        .map(|position_accounts| {
+            protect_position(ctx.accounts, position_accounts, action_type, &collateral_price, current_time)
+        })
+        .collect()
+}
+
+// Helper functions
+
+fn protect_position<'info>(
+    accounts: &mut ExecuteProtectionBatch<'info>,
+    position_accounts: &'info [AccountInfo<'info>],
+    action_type: ProtectionActionType,
+    collateral_price: &CollateralPrice,
+    current_time: i64,
+) -> Result<BatchProtectionResult> {
+    let mut protection_account = Account::<ProtectionAccount>::try_from(&position_accounts[0])?;
+    let protection_key = protection_account.key();
+    let owner = protection_account.owner;
+    let action_record = &position_accounts[4];
+    let addresses = PositionAddresses {
+        protection_account: protection_key,
+        user_collateral: position_accounts[1].key(),
+        user_stablecoin: position_accounts[2].key(),
+        action_record: action_record.key(),
+    };
+    let action_bump = addresses.verify(
+        &protection_account,
+        accounts.stablecoin_config.key(),
+        accounts.stablecoin_config.mint,
+        accounts.collateral_mint.key(),
+    )?;
+
+    let executed_by_keeper = executed_by_keeper(accounts.authority.key(), owner, accounts.keeper.is_some())?;
+
+    let mut user_collateral = Account::<UserCollateral>::try_from(&position_accounts[1])?;
+    let mut user_stablecoin = Account::<UserStablecoin>::try_from(&position_accounts[2])?;
+
+    let source_token_account = Account::<TokenAccount>::try_from(&position_accounts[3])?;
+    match action_type {
+        ProtectionActionType::AddCollateral => require_keys_eq!(
+            source_token_account.key(),
+            protection_account.config.auto_collateral_source,
+            ErrorCode::InvalidBatchAccounts
+        ),
+        _ => require!(
+            source_token_account.mint == protection_account.stablecoin_mint && source_token_account.owner == owner,
+            ErrorCode::InvalidBatchAccounts
+        ),
+    }
+
+    let position = PositionHealth::load(&user_collateral, &user_stablecoin, collateral_price)?;
+    let health_ratio_before = position.health_ratio()?;
+    let mut result = BatchProtectionResult {
+        protection_account: protection_key,
+        status: BatchProtectionStatus::Executed,
+        amount: 0,
+        health_ratio_before,
+        health_ratio_after: health_ratio_before,
+    };
+
+    // Same checks as `execute_protection_action`, reported instead of failing
+    let protection_amount = match protection_account.plan_protection(
+        &position,
+        health_ratio_before,
+        action_type,
+        collateral_price,
+        current_time,
+    )? {
+        ProtectionPlan::Skip(skip) => {
+            result.status = skip.into();
+            return Ok(result);
+        }
+        ProtectionPlan::Execute(amount) => amount,
+        ProtectionPlan::NotifyOnly => {
+            protection_account.record_notification(current_time, health_ratio_before);
+            let record = new_action_record(
+                &mut protection_account,
+                accounts.authority.key(),
+                current_time,
+                action_type,
+                0,
+                health_ratio_before,
+                health_ratio_before,
+                false,
+                action_bump,
+            )?;
+            create_action_record(accounts, action_record, &record)?;
+            protection_account.exit(&crate::ID)?;
+
+            result.status = BatchProtectionStatus::NotificationOnly;
+            return Ok(result);
+        }
+    };
+
+    if check_delegated_source(&source_token_account, protection_key, protection_amount).is_err() {
+        result.status = BatchProtectionStatus::InsufficientFunds;
+        return Ok(result);
+    }
+
+    protection_account.roll_budget_window(current_time);
+
+    if action_type == ProtectionActionType::AutoRepay {
+        burn_as_delegate(
+            &protection_account,
+            &accounts.stablecoin_mint,
+            &source_token_account,
+            &accounts.token_program,
+            protection_amount,
+        )?;
+
+        user_stablecoin.amount = user_stablecoin.amount.checked_sub(protection_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        let stablecoin_config = &mut accounts.stablecoin_config;
+        stablecoin_config.total_supply = stablecoin_config.total_supply.checked_sub(protection_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+    } else {
+        transfer_as_delegate(
+            &protection_account,
+            &source_token_account,
+            &accounts.destination_token_account,
+            &accounts.token_program,
+            protection_amount,
+        )?;
+
+        user_collateral.amount = user_collateral.amount.checked_add(protection_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        protection_account.custodied_collateral = protection_account.custodied_collateral.checked_add(protection_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+
+    let health_ratio_after = PositionHealth::load(&user_collateral, &user_stablecoin, collateral_price)?.health_ratio()?;
+
+    let value_moved = collateral_price.value_moved(action_type, protection_amount)?;
+    protection_account.record_execution(current_time, protection_amount, value_moved, health_ratio_after)?;
+    let record = new_action_record(
+        &mut protection_account,
+        accounts.authority.key(),
+        current_time,
+        action_type,
+        protection_amount,
+        health_ratio_before,
+        health_ratio_after,
+        true,
+        action_bump,
+    )?;
+    create_action_record(accounts, action_record, &record)?;
+
+    // Compensate the keeper from the protocol fund
+    if executed_by_keeper {
+        reward_executing_keeper(
+            accounts.keeper.as_deref_mut(),
+            accounts.keeper_registry.as_deref_mut(),
+            &accounts.authority.to_account_info(),
+            value_moved,
+            protection_account.config.cooldown_period,
+        )?;
+    }
+
+    protection_account.exit(&crate::ID)?;
+    user_collateral.exit(&crate::ID)?;
+    user_stablecoin.exit(&crate::ID)?;
+
+    result.amount = protection_amount;
+    result.health_ratio_after = health_ratio_after;
+    Ok(result)
+}
+
+// Creates the account for an action record at its PDA, paid by the authority. Only positions
+// the batch acts on get a record, so the address arrives unchecked and cannot use Anchor's
+// `init`. Like `init`, an address someone has already sent lamports to is topped up to rent
+// exemption, then allocated and assigned, rather than failing to be created.
+fn create_action_record<'info>(
+    accounts: &ExecuteProtectionBatch<'info>,
+    action_record: &AccountInfo<'info>,
+    record: &ProtectionActionRecord,
+) -> Result<()> {
+    let action_id_bytes = record.action_id.to_le_bytes();
+    let seeds = &[
+        b"protection-action",
+        record.protection_account.as_ref(),
+        action_id_bytes.as_ref(),
+        &[record.bump],
+    ];
+
+    let system_program = accounts.system_program.to_account_info();
+    let rent = Rent::get()?.minimum_balance(ProtectionActionRecord::SPACE);
+    let current_lamports = action_record.lamports();
+    if current_lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program,
+                system_program::CreateAccount {
+                    from: accounts.authority.to_account_info(),
+                    to: action_record.clone(),
+                },
+                &[&seeds[..]],
+            ),
+            rent,
+            ProtectionActionRecord::SPACE as u64,
+            &crate::ID,
+        )?;
+    } else {
+        require_keys_eq!(*action_record.owner, system_program::ID, ErrorCode::InvalidBatchAccounts);
+
+        let shortfall = rent.saturating_sub(current_lamports);
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer {
+                        from: accounts.authority.to_account_info(),
+                        to: action_record.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Allocate {
+                    account_to_allocate: action_record.clone(),
+                },
+                &[&seeds[..]],
+            ),
+            ProtectionActionRecord::SPACE as u64,
+        )?;
+
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program,
+                system_program::Assign {
+                    account_to_assign: action_record.clone(),
+                },
+                &[&seeds[..]],
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    let mut data = action_record.try_borrow_mut_data()?;
+    record.try_serialize(&mut &mut data[..])
+}
+
+// Addresses of a position's accounts in a batch
+struct PositionAddresses {
+    protection_account: Pubkey,
+    user_collateral: Pubkey,
+    user_stablecoin: Pubkey,
+    action_record: Pubkey,
+}
+
+impl PositionAddresses {
+    // Checks the addresses against the seeds single execution enforces through its account
+    // constraints. Returns the bump of the action record for the next action.
+    fn verify(
+        &self,
+        protection_account: &ProtectionAccount,
+        stablecoin_config: Pubkey,
+        stablecoin_mint: Pubkey,
+        collateral_mint: Pubkey,
+    ) -> Result<u8> {
+        let owner = protection_account.owner;
+        let expected = Pubkey::create_program_address(&protection_account.signer_seeds(), &crate::ID)
+            .map_err(|_| ErrorCode::InvalidBatchAccounts)?;
+        require_keys_eq!(expected, self.protection_account, ErrorCode::InvalidBatchAccounts);
+        require!(
+            protection_account.stablecoin_mint == stablecoin_mint && protection_account.collateral_mint == collateral_mint,
+            ErrorCode::InvalidBatchAccounts
+        );
+
+        let (expected, _) = Pubkey::find_program_address(
+            &[b"user-collateral", owner.as_ref(), stablecoin_config.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(expected, self.user_collateral, ErrorCode::InvalidBatchAccounts);
+
+        let (expected, _) = Pubkey::find_program_address(
+            &[b"user-stablecoin", owner.as_ref(), stablecoin_config.as_ref()],
+            &crate::ID,
+        );
+        require_keys_eq!(expected, self.user_stablecoin, ErrorCode::InvalidBatchAccounts);
+
+        let (expected, action_bump) = Pubkey::find_program_address(
+            &[
+                b"protection-action",
+                self.protection_account.as_ref(),
+                protection_account.next_action_id.to_le_bytes().as_ref(),
+            ],
+            &crate::ID,
+        );
+        require_keys_eq!(expected, self.action_record, ErrorCode::InvalidBatchAccounts);
+
+        Ok(action_bump)
+    }
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Batches support auto-repay and add collateral only")]
+    BatchActionUnsupported,
+    #[msg("Batch accounts do not match the positions")]
+    InvalidBatchAccounts,
+    #[msg("Too many positions for one batch")]
+    BatchTooLarge,
+    #[msg("Keeper is not active")]
+    KeeperInactive,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::liquidation_protection::{ProtectionConfig, ProtectionMode, PROTECTION_LAYOUT_VERSION};
+
+    fn protection_account(owner: Pubkey, stablecoin_mint: Pubkey, collateral_mint: Pubkey) -> ProtectionAccount {
+        let (_, bump) = Pubkey::find_program_address(
+            &[b"protection", owner.as_ref(), stablecoin_mint.as_ref(), collateral_mint.as_ref()],
+            &crate::ID,
+        );
+        ProtectionAccount {
+            owner,
+            stablecoin_mint,
+            collateral_mint,
+            config: ProtectionConfig {
+                trigger_ratio_bps: 12_000,
+                target_ratio_bps: 15_000,
+                protection_mode: ProtectionMode::All,
+                max_protection_amount: u64::MAX,
+                notification_only: false,
+                cooldown_period: 0,
+                auto_collateral_source: Pubkey::new_unique(),
+                budget_amount: 1_000_000_000,
+                budget_window: 86_400,
+            },
+            is_active: true,
+            last_protection_time: 0,
+            total_protection_actions: 0,
+            total_protected_amount: 0,
+            last_health_ratio: 0,
+            next_action_id: 3,
+            open_action_records: 0,
+            budget_window_start: 0,
+            budget_spent: 0,
+            custodied_collateral: 0,
+            bump,
+            version: PROTECTION_LAYOUT_VERSION,
+        }
+    }
+
+    // The addresses single execution would derive for the position
+    fn addresses(protection_account: &ProtectionAccount, stablecoin_config: Pubkey, action_id: u64) -> PositionAddresses {
+        let owner = protection_account.owner;
+        let protection_key = Pubkey::create_program_address(&protection_account.signer_seeds(), &crate::ID).unwrap();
+        PositionAddresses {
+            protection_account: protection_key,
+            user_collateral: Pubkey::find_program_address(
+                &[b"user-collateral", owner.as_ref(), stablecoin_config.as_ref()],
+                &crate::ID,
+            )
+            .0,
+            user_stablecoin: Pubkey::find_program_address(
+                &[b"user-stablecoin", owner.as_ref(), stablecoin_config.as_ref()],
+                &crate::ID,
+            )
+            .0,
+            action_record: Pubkey::find_program_address(
+                &[b"protection-action", protection_key.as_ref(), action_id.to_le_bytes().as_ref()],
+                &crate::ID,
+            )
+            .0,
+        }
+    }
+
+    #[test]
+    fn position_addresses_match_the_single_execution_seeds() {
+        let (stablecoin_config, stablecoin_mint, collateral_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let account = protection_account(Pubkey::new_unique(), stablecoin_mint, collateral_mint);
+        let addresses = addresses(&account, stablecoin_config, account.next_action_id);
+
+        let (_, action_bump) = Pubkey::find_program_address(
+            &[b"protection-action", addresses.protection_account.as_ref(), 3u64.to_le_bytes().as_ref()],
+            &crate::ID,
+        );
+        assert_eq!(
+            addresses.verify(&account, stablecoin_config, stablecoin_mint, collateral_mint).unwrap(),
+            action_bump
+        );
+    }
+
+    #[test]
+    fn mismatched_position_addresses_are_rejected() {
+        let (stablecoin_config, stablecoin_mint, collateral_mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let account = protection_account(Pubkey::new_unique(), stablecoin_mint, collateral_mint);
+        let other_owner = addresses(
+            &protection_account(Pubkey::new_unique(), stablecoin_mint, collateral_mint),
+            stablecoin_config,
+            3,
+        );
+        let other_config = addresses(&account, Pubkey::new_unique(), 3);
+        let mismatched = [
+            PositionAddresses { protection_account: other_owner.protection_account, ..addresses(&account, stablecoin_config, 3) },
+            PositionAddresses { user_collateral: other_owner.user_collateral, ..addresses(&account, stablecoin_config, 3) },
+            PositionAddresses { user_stablecoin: other_config.user_stablecoin, ..addresses(&account, stablecoin_config, 3) },
+            // Records go to the next action id only
+            addresses(&account, stablecoin_config, 2),
+        ];
+        for addresses in mismatched {
+            assert_eq!(
+                addresses.verify(&account, stablecoin_config, stablecoin_mint, collateral_mint).unwrap_err(),
+                ErrorCode::InvalidBatchAccounts.into()
+            );
+        }
+
+        // The protection account must cover the batch's mints
+        let addresses = addresses(&account, stablecoin_config, 3);
+        assert_eq!(
+            addresses.verify(&account, stablecoin_config, stablecoin_mint, Pubkey::new_unique()).unwrap_err(),
+            ErrorCode::InvalidBatchAccounts.into()
+        );
+
+        // A bump other than the canonical one derives another address
+        let mut tampered = protection_account(account.owner, stablecoin_mint, collateral_mint);
+        tampered.bump = tampered.bump.wrapping_sub(1);
+        assert_eq!(
+            addresses.verify(&tampered, stablecoin_config, stablecoin_mint, collateral_mint).unwrap_err(),
+            ErrorCode::InvalidBatchAccounts.into()
+        );
+    }
+
+    #[test]
+    fn skipped_positions_report_why() {
+        let statuses = [
+            (ProtectionSkip::Inactive, BatchProtectionStatus::Inactive),
+            (ProtectionSkip::NotNeeded, BatchProtectionStatus::NotNeeded),
+            (ProtectionSkip::ActionNotAllowed, BatchProtectionStatus::ActionNotAllowed),
+            (ProtectionSkip::CooldownActive, BatchProtectionStatus::CooldownActive),
+            (ProtectionSkip::BudgetExhausted, BatchProtectionStatus::BudgetExhausted),
+        ];
+        for (skip, status) in statuses {
+            assert_eq!(BatchProtectionStatus::from(skip), status);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+
+use crate::liquidation_protection::{
+    ProtectionAccount, ProtectionActionRecord, ProtectionActionType, ProtectionConfig, ProtectionMode,
+    HEALTH_RATIO_SCALE, PROTECTION_LAYOUT_VERSION,
+};
+
+// Layouts deployed before protection modes and action types were typed. Legacy accounts
+// stored a placeholder bump of 255, so their addresses are checked against the canonical
+// bump instead.
+
+#[derive(AnchorDeserialize)]
+struct LegacyProtectionConfig {
+    threshold_percentage: u8, // Health ratio threshold % (e.g., 120 = 120%)
+    protection_mode: u8,      // 1 = Auto-repay, 2 = Add collateral, 3 = Both
+    max_protection_amount: u64,
+    notification_only: bool,
+    cooldown_period: i64,
+    auto_collateral_source: Pubkey,
+}
+
+#[derive(AnchorDeserialize)]
+struct LegacyProtectionAccount {
+    owner: Pubkey,
+    stablecoin_mint: Pubkey,
+    collateral_mint: Pubkey,
+    config: LegacyProtectionConfig,
+    _is_active: bool,
+    last_protection_time: i64,
+    total_protection_actions: u64,
+    total_protected_amount: u64,
+    last_health_ratio: u16, // Scaled by 100
+    _bump: u8,
+}
+
+// Legacy records were seeded by their protection account alone, one per account
+#[derive(AnchorDeserialize)]
+struct LegacyProtectionActionRecord {
+    protection_account: Pubkey,
+    timestamp: i64,
+    action_type: u8, // 1 = Auto-repay, 2 = Add collateral
+    amount: u64,
+    health_ratio_before: u16, // Scaled by 100
+    health_ratio_after: u16,  // Scaled by 100
+    success: bool,
+    _bump: u8,
+}
+
+const LEGACY_ACTION_RECORD_SPACE: usize = 8 + 32 + 8 + 1 + 8 + 2 + 2 + 1 + 1;
+
+// A legacy record moves to the first action id, so migrated accounts continue after it
+const LEGACY_ACTION_ID: u64 = 0;
+
+// Legacy protection restored positions to the threshold itself, which the current layout
+// rejects. Migrated accounts target this far above their trigger.
+const LEGACY_TARGET_MARGIN_BPS: u16 = 1_000;
+
+// Legacy health ratios were percentages
+const LEGACY_RATIO_TO_BPS: u16 = 100;
+
+fn mode_from_legacy(protection_mode: u8) -> Option<ProtectionMode> {
+    match protection_mode {
+        1 => Some(ProtectionMode::AutoRepay),
+        2 => Some(ProtectionMode::AddCollateral),
+        3 => Some(ProtectionMode::Both),
+        _ => None,
+    }
+}
+
+fn action_type_from_legacy(action_type: u8) -> Option<ProtectionActionType> {
+    match action_type {
+        1 => Some(ProtectionActionType::AutoRepay),
+        2 => Some(ProtectionActionType::AddCollateral),
+        _ => None,
+    }
+}
+
+fn ratio_from_legacy(ratio: u16) -> u16 {
+    ratio.saturating_mul(LEGACY_RATIO_TO_BPS)
+}
+
+// The legacy threshold becomes the trigger ratio, with the target above it
+fn ratios_from_legacy_threshold(threshold_percentage: u8) -> Result<(u16, u16)> {
+    let trigger_ratio_bps = ratio_from_legacy(threshold_percentage as u16);
+    require!(trigger_ratio_bps as u64 > HEALTH_RATIO_SCALE, ErrorCode::InvalidLegacyAccount);
+    let target_ratio_bps = trigger_ratio_bps.checked_add(LEGACY_TARGET_MARGIN_BPS).ok_or(ErrorCode::InvalidLegacyAccount)?;
+
+    Ok((trigger_ratio_bps, target_ratio_bps))
+}
+
+// Legacy accounts had no budget, so the owner sets one, and they were activated against a
+// placeholder health ratio, so they come back inactive until the owner activates them
+// against the oracle
+fn account_from_legacy(
+    legacy: LegacyProtectionAccount,
+    bump: u8,
+    budget_amount: u64,
+    budget_window: i64,
+) -> Result<ProtectionAccount> {
+    let (trigger_ratio_bps, target_ratio_bps) = ratios_from_legacy_threshold(legacy.config.threshold_percentage)?;
+
+    Ok(ProtectionAccount {
+        owner: legacy.owner,
+        stablecoin_mint: legacy.stablecoin_mint,
+        collateral_mint: legacy.collateral_mint,
+        config: ProtectionConfig {
+            trigger_ratio_bps,
+            target_ratio_bps,
+            protection_mode: mode_from_legacy(legacy.config.protection_mode).ok_or(ErrorCode::InvalidLegacyAccount)?,
+            max_protection_amount: legacy.config.max_protection_amount,
+            notification_only: legacy.config.notification_only,
+            cooldown_period: legacy.config.cooldown_period,
+            auto_collateral_source: legacy.config.auto_collateral_source,
+            budget_amount,
+            budget_window,
+        },
+        is_active: false,
+        last_protection_time: legacy.last_protection_time,
+        total_protection_actions: legacy.total_protection_actions,
+        total_protected_amount: legacy.total_protected_amount,
+        last_health_ratio: ratio_from_legacy(legacy.last_health_ratio),
+        next_action_id: LEGACY_ACTION_ID + 1,
+        open_action_records: 0,
+        budget_window_start: 0,
+        budget_spent: 0,
+        custodied_collateral: 0,
+        bump,
+        version: PROTECTION_LAYOUT_VERSION,
+    })
+}
+
+fn action_record_from_legacy(legacy: LegacyProtectionActionRecord, payer: Pubkey, bump: u8) -> Result<ProtectionActionRecord> {
+    Ok(ProtectionActionRecord {
+        protection_account: legacy.protection_account,
+        action_id: LEGACY_ACTION_ID,
+        payer,
+        timestamp: legacy.timestamp,
+        action_type: action_type_from_legacy(legacy.action_type).ok_or(ErrorCode::InvalidLegacyAccount)?,
+        amount: legacy.amount,
+        health_ratio_before: ratio_from_legacy(legacy.health_ratio_before),
+        health_ratio_after: ratio_from_legacy(legacy.health_ratio_after),
+        success: legacy.success,
+        bump,
+        version: PROTECTION_LAYOUT_VERSION,
+    })
+}
+
+#[derive(Accounts)]
+pub struct MigrateProtectionAccount<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Still in the legacy layout, verified by discriminator, owner and seeds in the handler
+    #[account(mut, owner = crate::ID)]
+    pub protection_account: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Moves a legacy record to the current seeds. Legacy records did not keep who paid for
+// them, often a keeper rather than the owner, so that payer signs alongside the owner. It
+// pays for the new record, which refunds it when closed, and takes back the legacy rent.
+#[derive(Accounts)]
+pub struct MigrateProtectionActionRecord<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub legacy_payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"protection", owner.key().as_ref(), protection_account.stablecoin_mint.as_ref(), protection_account.collateral_mint.as_ref()],
+        bump = protection_account.bump,
+        has_one = owner
+    )]
+    pub protection_account: Account<'info, ProtectionAccount>,
+
+    /// CHECK: Still in the legacy layout, verified by discriminator and seeds in the handler
+    #[account(mut, owner = crate::ID)]
+    pub legacy_action_record: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = legacy_payer,
+        space = ProtectionActionRecord::SPACE,
+        seeds = [b"protection-action", protection_account.key().as_ref(), LEGACY_ACTION_ID.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub action_record: Account<'info, ProtectionActionRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Implementation of functions
+
+// Rewrites a legacy protection account in the current layout, see `account_from_legacy`
+pub fn migrate_protection_account(
+    ctx: Context<MigrateProtectionAccount>,
+    budget_amount: u64,
+    budget_window: i64,
+) -> Result<()> {
+    require!(budget_amount > 0 && budget_window > 0, ErrorCode::InvalidBudget);
+
+    let account_info = ctx.accounts.protection_account.to_account_info();
+    let legacy = {
+        let data = account_info.try_borrow_data()?;
+        // The legacy space was hand-counted, so any other length is left to deserialization
+        require!(data.len() != ProtectionAccount::SPACE, ErrorCode::AlreadyMigrated);
+        require!(&data[..8] == ProtectionAccount::DISCRIMINATOR, ErrorCode::InvalidLegacyAccount);
+        LegacyProtectionAccount::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidLegacyAccount)?
+    };
+
+    require_keys_eq!(legacy.owner, ctx.accounts.owner.key(), ErrorCode::Unauthorized);
+    let (expected, bump) = Pubkey::find_program_address(
+        &[
+            b"protection",
+            legacy.owner.as_ref(),
+            legacy.stablecoin_mint.as_ref(),
+            legacy.collateral_mint.as_ref(),
+        ],
+        &crate::ID,
+    );
+    require_keys_eq!(expected, account_info.key(), ErrorCode::InvalidLegacyAccount);
+
+    let migrated = account_from_legacy(legacy, bump, budget_amount, budget_window)?;
+
+    grow_account(
+        &account_info,
+        &ctx.accounts.owner.to_account_info(),
+        &ctx.accounts.system_program,
+        ProtectionAccount::SPACE,
+    )?;
+    let mut data = account_info.try_borrow_mut_data()?;
+    migrated.try_serialize(&mut &mut data[..])
+}
+
+// Copies a legacy record to the first action id of its migrated protection account and
+// closes the legacy record
+pub fn migrate_protection_action_record(ctx: Context<MigrateProtectionActionRecord>) -> Result<()> {
+    let protection_key = ctx.accounts.protection_account.key();
+    let legacy_info = ctx.accounts.legacy_action_record.to_account_info();
+    let legacy = {
+        let data = legacy_info.try_borrow_data()?;
+        require!(data.len() == LEGACY_ACTION_RECORD_SPACE, ErrorCode::AlreadyMigrated);
+        require!(&data[..8] == ProtectionActionRecord::DISCRIMINATOR, ErrorCode::InvalidLegacyAccount);
+        LegacyProtectionActionRecord::deserialize(&mut &data[8..])?
+    };
+
+    require_keys_eq!(legacy.protection_account, protection_key, ErrorCode::InvalidLegacyAccount);
+    let (expected, _) = Pubkey::find_program_address(&[b"protection-action", protection_key.as_ref()], &crate::ID);
+    require_keys_eq!(expected, legacy_info.key(), ErrorCode::InvalidLegacyAccount);
+
+    let payer = ctx.accounts.legacy_payer.key();
+    ctx.accounts.action_record.set_inner(action_record_from_legacy(legacy, payer, ctx.bumps.action_record)?);
+
+    let protection_account = &mut ctx.accounts.protection_account;
+    protection_account.open_action_records = protection_account.open_action_records.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    close_account(&legacy_info, &ctx.accounts.legacy_payer.to_account_info())
+}
+
+// Helper functions
+
+// Resizes a program-owned account, topping its rent up from `payer`
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+) -> Result<()> {
+    let shortfall = Rent::get()?.minimum_balance(space).saturating_sub(account.lamports());
+    if shortfall > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            shortfall,
+        )?;
+    }
+
+    account.realloc(space, false)?;
+    Ok(())
+}
+
+// Closes a program-owned account that no longer deserializes, refunding its rent to `destination`
+fn close_account<'info>(account: &AccountInfo<'info>, destination: &AccountInfo<'info>) -> Result<()> {
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination.lamports().checked_add(lamports).ok_or(ErrorCode::InvalidLegacyAccount)?;
+    **account.try_borrow_mut_lamports()? = 0;
+
+    account.assign(&system_program::ID);
+    account.realloc(0, false)?;
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Account is already in the current layout")]
+    AlreadyMigrated,
+    #[msg("Account is not a legacy protection account or action record")]
+    InvalidLegacyAccount,
+    #[msg("Only the protection owner can migrate the account")]
+    Unauthorized,
+    #[msg("Budget amount and window must be positive")]
+    InvalidBudget,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Legacy protection account data after the discriminator, field by field in the
+    // deployed layout
+    fn legacy_account_data(owner: Pubkey, threshold_percentage: u8, protection_mode: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(owner.as_ref());
+        data.extend_from_slice(Pubkey::new_from_array([2; 32]).as_ref()); // stablecoin_mint
+        data.extend_from_slice(Pubkey::new_from_array([3; 32]).as_ref()); // collateral_mint
+        data.push(threshold_percentage);
+        data.push(protection_mode);
+        data.extend_from_slice(&5_000u64.to_le_bytes()); // max_protection_amount
+        data.push(0); // notification_only
+        data.extend_from_slice(&3_600i64.to_le_bytes()); // cooldown_period
+        data.extend_from_slice(Pubkey::new_from_array([4; 32]).as_ref()); // auto_collateral_source
+        data.push(1); // is_active
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // last_protection_time
+        data.extend_from_slice(&7u64.to_le_bytes()); // total_protection_actions
+        data.extend_from_slice(&12_345u64.to_le_bytes()); // total_protected_amount
+        data.extend_from_slice(&115u16.to_le_bytes()); // last_health_ratio
+        data.push(255); // bump
+        data
+    }
+
+    fn legacy_action_record_data(protection_account: Pubkey, action_type: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(protection_account.as_ref());
+        data.extend_from_slice(&1_700_000_000i64.to_le_bytes()); // timestamp
+        data.push(action_type);
+        data.extend_from_slice(&2_500u64.to_le_bytes()); // amount
+        data.extend_from_slice(&110u16.to_le_bytes()); // health_ratio_before
+        data.extend_from_slice(&125u16.to_le_bytes()); // health_ratio_after
+        data.push(1); // success
+        data.push(255); // bump
+        data
+    }
+
+    fn legacy_account(threshold_percentage: u8, protection_mode: u8) -> LegacyProtectionAccount {
+        let data = legacy_account_data(Pubkey::new_unique(), threshold_percentage, protection_mode);
+        LegacyProtectionAccount::deserialize(&mut &data[..]).unwrap()
+    }
+
+    #[test]
+    fn legacy_ratios_are_percentages() {
+        assert_eq!(ratio_from_legacy(120), 12_000);
+        assert_eq!(ratio_from_legacy(0), 0);
+        assert_eq!(ratio_from_legacy(u16::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn legacy_threshold_becomes_the_trigger_below_the_target() {
+        assert_eq!(ratios_from_legacy_threshold(120).unwrap(), (12_000, 13_000));
+        assert_eq!(ratios_from_legacy_threshold(101).unwrap(), (10_100, 11_100));
+    }
+
+    #[test]
+    fn legacy_thresholds_at_or_below_one_hundred_percent_are_rejected() {
+        for threshold_percentage in [0, 99, 100] {
+            assert_eq!(
+                ratios_from_legacy_threshold(threshold_percentage).unwrap_err(),
+                ErrorCode::InvalidLegacyAccount.into()
+            );
+        }
+        assert_eq!(
+            account_from_legacy(legacy_account(100, 1), 254, 1_000, 86_400).err().unwrap(),
+            ErrorCode::InvalidLegacyAccount.into()
+        );
+    }
+
+    #[test]
+    fn legacy_account_migrates_from_the_deployed_layout() {
+        let owner = Pubkey::new_unique();
+        let data = legacy_account_data(owner, 120, 3);
+        let legacy = LegacyProtectionAccount::deserialize(&mut &data[..]).unwrap();
+        let migrated = account_from_legacy(legacy, 254, 1_000, 86_400).unwrap();
+
+        assert_eq!(migrated.owner, owner);
+        assert_eq!(migrated.stablecoin_mint, Pubkey::new_from_array([2; 32]));
+        assert_eq!(migrated.collateral_mint, Pubkey::new_from_array([3; 32]));
+        assert_eq!((migrated.config.trigger_ratio_bps, migrated.config.target_ratio_bps), (12_000, 13_000));
+        assert_eq!(migrated.config.protection_mode, ProtectionMode::Both);
+        assert_eq!(migrated.config.max_protection_amount, 5_000);
+        assert!(!migrated.config.notification_only);
+        assert_eq!(migrated.config.cooldown_period, 3_600);
+        assert_eq!(migrated.config.auto_collateral_source, Pubkey::new_from_array([4; 32]));
+        assert_eq!((migrated.config.budget_amount, migrated.config.budget_window), (1_000, 86_400));
+        assert!(!migrated.is_active);
+        assert_eq!(migrated.last_protection_time, 1_700_000_000);
+        assert_eq!((migrated.total_protection_actions, migrated.total_protected_amount), (7, 12_345));
+        assert_eq!(migrated.last_health_ratio, 11_500);
+        assert_eq!((migrated.next_action_id, migrated.open_action_records), (LEGACY_ACTION_ID + 1, 0));
+        assert_eq!((migrated.bump, migrated.version), (254, PROTECTION_LAYOUT_VERSION));
+
+        // The migrated account fills the current space exactly
+        let mut migrated_data = Vec::new();
+        migrated.try_serialize(&mut migrated_data).unwrap();
+        assert_eq!(migrated_data.len(), ProtectionAccount::SPACE);
+    }
+
+    #[test]
+    fn legacy_account_with_an_unknown_mode_is_rejected() {
+        assert_eq!(
+            account_from_legacy(legacy_account(120, 4), 254, 1_000, 86_400).err().unwrap(),
+            ErrorCode::InvalidLegacyAccount.into()
+        );
+    }
+
+    #[test]
+    fn legacy_action_record_migrates_from_the_deployed_layout() {
+        let protection_account = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let data = legacy_action_record_data(protection_account, 2);
+        assert_eq!(8 + data.len(), LEGACY_ACTION_RECORD_SPACE);
+
+        let legacy = LegacyProtectionActionRecord::deserialize(&mut &data[..]).unwrap();
+        let record = action_record_from_legacy(legacy, payer, 253).unwrap();
+
+        assert_eq!(record.protection_account, protection_account);
+        assert_eq!((record.action_id, record.payer), (LEGACY_ACTION_ID, payer));
+        assert_eq!(record.timestamp, 1_700_000_000);
+        assert_eq!(record.action_type, ProtectionActionType::AddCollateral);
+        assert_eq!(record.amount, 2_500);
+        assert_eq!((record.health_ratio_before, record.health_ratio_after), (11_000, 12_500));
+        assert!(record.success);
+        assert_eq!((record.bump, record.version), (253, PROTECTION_LAYOUT_VERSION));
+
+        let data = legacy_action_record_data(protection_account, 3);
+        let legacy = LegacyProtectionActionRecord::deserialize(&mut &data[..]).unwrap();
+        assert_eq!(
+            action_record_from_legacy(legacy, payer, 253).err().unwrap(),
+            ErrorCode::InvalidLegacyAccount.into()
+        );
+    }
+}
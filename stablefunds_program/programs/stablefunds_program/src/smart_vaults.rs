@@ -1,5 +1,25 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+
+use crate::global_config::{GlobalConfig, MAX_BPS};
+use crate::oracle::PriceFeed;
+use crate::yield_history::YieldHistory;
+
+pub const PRICE_PER_SHARE_SCALE: u64 = 1_000_000_000;
+pub const MAX_STRATEGIES: usize = 10;
+pub const MAX_VAULT_NAME_LEN: usize = 32;
+pub const MIN_RISK_LEVEL: u8 = 1;
+pub const MAX_RISK_LEVEL: u8 = 5;
+// Risk scores are allocation-weighted tiers scaled by 100 (e.g., 250 = tier 2.5)
+pub const RISK_SCORE_SCALE: u64 = 100;
+// Added to the score of a vault fully concentrated in one strategy, scaled down with diversification
+pub const MAX_CONCENTRATION_PENALTY: u64 = 50;
+pub const CONSERVATIVE_MAX_SCORE: u16 = 200;
+pub const MODERATE_MAX_SCORE: u16 = 350;
+// Haircut on vault shares used as stablecoin collateral, indexed by risk level - 1
+pub const SHARE_COLLATERAL_HAIRCUT_BPS: [u16; 5] = [500, 1_000, 1_500, 2_500, 3_500];
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct SmartVaultParams {
@@ -11,27 +31,287 @@ pub struct SmartVaultParams {
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct StrategyAllocation {
     pub strategy_id: u8,
-    pub allocation_percentage: u8, // 0-100
+    pub allocation_bps: u16, // In basis points, allocations sum to 10_000
+}
+
+impl StrategyAllocation {
+    pub const SIZE: usize = 1 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RiskProfile {
+    Conservative,
+    Moderate,
+    Aggressive,
+}
+
+impl RiskProfile {
+    pub fn from_score(risk_score: u16) -> Self {
+        if risk_score <= CONSERVATIVE_MAX_SCORE {
+            RiskProfile::Conservative
+        } else if risk_score <= MODERATE_MAX_SCORE {
+            RiskProfile::Moderate
+        } else {
+            RiskProfile::Aggressive
+        }
+    }
+}
+
+// Returned by `get_vault_risk`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct VaultRiskReport {
+    pub risk_level: u8,
+    pub risk_score: u16,
+    pub profile: RiskProfile,
 }
 
 #[account]
 pub struct SmartVault {
     pub owner: Pubkey,
+    pub curator: Pubkey,  // Manages allocations and rebalances, never withdraws depositor funds
+    pub guardian: Pubkey, // Can only reduce risk by deallocating or pausing
     pub collateral_mint: Pubkey,
     pub vault_token_account: Pubkey,
+    pub share_mint: Pubkey,
     pub total_deposited: u64,
     pub total_allocated: u64,
     pub risk_level: u8,
+    pub risk_score: u16, // Allocation-weighted strategy tier plus concentration penalty, scaled by RISK_SCORE_SCALE
     pub auto_compound: bool,
     pub name: String,
     pub strategies: Vec<StrategyAllocation>,
     pub active: bool,
     pub last_update_time: i64,
     pub total_yield_earned: u64,
+    pub claimable_yield: u64, // Harvested yield held for the owner when not auto-compounding
+    pub fee_recipient: Pubkey,
+    pub management_fee_bps: u16,  // Annualized, accrued by time
+    pub performance_fee_bps: u16, // Charged on price-per-share gains above the high-water mark
+    pub high_water_mark: u64,     // Price per share, scaled by PRICE_PER_SHARE_SCALE
+    pub last_fee_time: i64,
+    pub queued_withdrawals: u64,   // Requested through withdrawal tickets, not yet fulfilled
+    pub reserved_withdrawals: u64, // Fulfilled tickets awaiting their claim
+    pub illiquid_allocated: u64,   // Principal restaked externally or held in other assets, out of reach of unwinding
+    pub next_withdrawal_ticket_id: u64,
+    pub tvl_cap: u64,     // Maximum total_deposited, further bounded by the protocol maximum
+    pub user_cap: u64,    // Maximum value of a single depositor's shares
+    pub min_deposit: u64, // Further bounded below by the protocol floor
+    pub strategy_adapter_count: u16, // Registered adapters not yet closed
+    pub bump: u8,
+}
+
+impl SmartVault {
+    pub fn space(name: &str, strategy_count: usize) -> usize {
+        8 + // discriminator
+        32 + // owner
+        32 + // curator
+        32 + // guardian
+        32 + // collateral_mint
+        32 + // vault_token_account
+        32 + // share_mint
+        8 + // total_deposited
+        8 + // total_allocated
+        1 + // risk_level
+        2 + // risk_score
+        1 + // auto_compound
+        4 + name.len() + // name
+        4 + strategy_count * StrategyAllocation::SIZE + // strategies
+        1 + // active
+        8 + // last_update_time
+        8 + // total_yield_earned
+        8 + // claimable_yield
+        32 + // fee_recipient
+        2 + // management_fee_bps
+        2 + // performance_fee_bps
+        8 + // high_water_mark
+        8 + // last_fee_time
+        8 + // queued_withdrawals
+        8 + // reserved_withdrawals
+        8 + // illiquid_allocated
+        8 + // next_withdrawal_ticket_id
+        8 + // tvl_cap
+        8 + // user_cap
+        8 + // min_deposit
+        2 + // strategy_adapter_count
+        1 // bump
+    }
+    
+    // Vault token balance not owed to the owner's yield or to fulfilled withdrawal tickets
+    pub fn free_idle_balance(&self, vault_token_account: &TokenAccount) -> u64 {
+        vault_token_account
+            .amount
+            .saturating_sub(self.claimable_yield)
+            .saturating_sub(self.reserved_withdrawals)
+    }
+    
+    // Deposits that unwinding can pay out. Illiquid principal has to come back through its
+    // adapter before it can leave the vault.
+    pub fn withdrawable(&self) -> u64 {
+        self.total_deposited.saturating_sub(self.illiquid_allocated)
+    }
+    
+    // Takes a withdrawal, paid now or queued, out of the vault's deposits
+    pub fn withdraw_deposits(&mut self, amount: u64) -> Result<()> {
+        require!(amount <= self.withdrawable(), ErrorCode::WithdrawalExceedsLiquidity);
+        self.total_deposited -= amount;
+        Ok(())
+    }
+    
+    // Queued withdrawals stay deployed until a ticket is filled. They are already out of
+    // `total_deposited`, so keeping illiquid principal within it leaves them unwindable.
+    pub fn queue_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.withdraw_deposits(amount)?;
+        self.queued_withdrawals = self.queued_withdrawals.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    // Moves a filled ticket's amount from the queue into the reserved idle balance
+    pub fn reserve_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.queued_withdrawals = self.queued_withdrawals.checked_sub(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        self.reserved_withdrawals = self.reserved_withdrawals.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    // Releases a claimed ticket's reservation
+    pub fn release_withdrawal(&mut self, amount: u64) -> Result<()> {
+        self.reserved_withdrawals = self.reserved_withdrawals.checked_sub(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        Ok(())
+    }
+    
+    // Principal moving out of reach of unwinding, bounded by the deposits left after
+    // queued withdrawals
+    pub fn add_illiquid(&mut self, amount: u64) -> Result<()> {
+        let illiquid_allocated = self.illiquid_allocated.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(illiquid_allocated <= self.total_deposited, ErrorCode::IlliquidAllocationExceeded);
+        self.illiquid_allocated = illiquid_allocated;
+        Ok(())
+    }
+    
+    pub fn remove_illiquid(&mut self, amount: u64) {
+        self.illiquid_allocated = self.illiquid_allocated.saturating_sub(amount);
+    }
+    
+    // Enforces the vault's deposit limits, bounded by the protocol's. `existing_position` is
+    // the value of the depositor's shares before the deposit.
+    pub fn check_deposit_limits(&self, amount: u64, existing_position: u64, global_config: &GlobalConfig) -> Result<()> {
+        let min_deposit = self.min_deposit.max(global_config.min_deposit_floor);
+        require!(amount > 0 && amount >= min_deposit, ErrorCode::DepositBelowMinimum);
+        
+        let new_total = self.total_deposited.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(
+            new_total <= self.tvl_cap.min(global_config.max_vault_tvl),
+            ErrorCode::VaultCapExceeded
+        );
+        
+        require!(
+            existing_position.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)? <= self.user_cap,
+            ErrorCode::UserCapExceeded
+        );
+        
+        Ok(())
+    }
+    
+    // Shares minted for a deposit at the current price per share. Once losses wipe out the
+    // deposits, the shares left over would claim most of any new deposit, so deposits are
+    // refused instead.
+    pub fn shares_for_deposit(&self, amount: u64, share_supply: u64) -> Result<u64> {
+        require!(share_supply == 0 || self.total_deposited > 0, ErrorCode::UnbackedShares);
+        if share_supply == 0 {
+            return Ok(amount);
+        }
+        mul_div(amount, share_supply, self.total_deposited)
+    }
+    
+    // Shares burned for a withdrawal, rounded up in favour of the remaining holders
+    pub fn shares_for_withdrawal(&self, amount: u64, share_supply: u64) -> Result<u64> {
+        require!(self.total_deposited > 0, ErrorCode::InsufficientFunds);
+        let shares = (amount as u128)
+            .checked_mul(share_supply as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            .div_ceil(self.total_deposited as u128);
+        u64::try_from(shares).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+    
+    pub fn price_per_share(&self, share_supply: u64) -> Result<u64> {
+        if share_supply == 0 {
+            return Ok(PRICE_PER_SHARE_SCALE);
+        }
+        mul_div(self.total_deposited, PRICE_PER_SHARE_SCALE, share_supply)
+    }
+    
+    // Management fee accrued at `management_fee_bps` a year since fees were last taken
+    pub fn accrued_management_fee(&self, management_fee_bps: u16, current_time: i64) -> Result<u64> {
+        let elapsed = current_time.saturating_sub(self.last_fee_time).max(0) as u64;
+        mul_div(apply_bps(self.total_deposited, management_fee_bps)?, elapsed, SECONDS_PER_YEAR)
+    }
+    
+    // Fees are paid by minting the fee recipient new shares worth exactly the fee
+    pub fn fee_shares(&self, fee: u64, share_supply: u64) -> Result<u64> {
+        let fee = fee.min(self.total_deposited);
+        if share_supply == 0 || fee == self.total_deposited {
+            return Ok(0);
+        }
+        mul_div(fee, share_supply, self.total_deposited - fee)
+    }
+    
+    // USD value of shares used as collateral: price per share times the underlying oracle
+    // price, less a haircut that grows with the vault's risk level
+    pub fn share_collateral_value(
+        &self,
+        shares: u64,
+        share_mint: &Mint,
+        underlying_price_feed: &PriceFeed,
+    ) -> Result<u64> {
+        require!(underlying_price_feed.mint == self.collateral_mint, ErrorCode::InvalidPriceFeed);
+        
+        // Share mints use the underlying mint's decimals
+        let underlying = mul_div(shares, self.price_per_share(share_mint.supply)?, PRICE_PER_SHARE_SCALE)?;
+        let value = underlying_price_feed.value_of(underlying, share_mint.decimals)?;
+        let risk_index = self.risk_level.clamp(MIN_RISK_LEVEL, MAX_RISK_LEVEL) - 1;
+        
+        apply_bps(value, MAX_BPS - SHARE_COLLATERAL_HAIRCUT_BPS[risk_index as usize])
+    }
+    
+    // Collateral value of one whole share, in the oracle's price scale
+    pub fn share_collateral_price(&self, share_mint: &Mint, underlying_price_feed: &PriceFeed) -> Result<u64> {
+        self.share_collateral_value(10u64.pow(share_mint.decimals as u32), share_mint, underlying_price_feed)
+    }
+}
+
+// Tracks the funds a smart vault has deployed into a single strategy
+#[account]
+pub struct StrategyAdapter {
+    pub smart_vault: Pubkey,
+    pub strategy_id: u8,
+    pub risk_tier: u8,                  // 1-5, may not exceed the risk level of vaults allocating to it
+    pub asset_mint: Pubkey,             // Underlying of the strategy, swapped into when it is not the vault's
+    pub position_token_account: Pubkey, // Vault-owned token account holding the deployed funds
+    pub deployed_amount: u64,           // Principal currently deployed into the strategy, in the vault's underlying
+    pub external_amount: u64,           // Part of the principal moved out to an external protocol, e.g. restaked
+    pub reported_value: u64,            // Value reported by the strategy at the last harvest
+    pub last_report_time: i64,
     pub bump: u8,
 }
 
+impl StrategyAdapter {
+    pub const SPACE: usize = 8 + 32 + 1 + 1 + 32 + 32 + 8 + 8 + 8 + 8 + 1;
+    
+    // Strategies in another asset are entered and exited through a swap adapter, so
+    // plain transfers between their position and the vault token account do not apply
+    pub fn holds_vault_underlying(&self, smart_vault: &SmartVault) -> bool {
+        self.asset_mint == smart_vault.collateral_mint
+    }
+
+    // Value of the position as reported by the strategy. Yield is paid into the position
+    // token account, so its balance plus anything held externally, valued at cost until
+    // it comes back, is the strategy's current value.
+    pub fn current_value(&self, position_token_account: &TokenAccount) -> u64 {
+        position_token_account.amount.saturating_add(self.external_amount)
+    }
+}
+
 #[derive(Accounts)]
+#[instruction(vault_params: SmartVaultParams)]
 pub struct InitializeSmartVault<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
@@ -41,7 +321,7 @@ pub struct InitializeSmartVault<'info> {
     #[account(
         init,
         payer = owner,
-        space = 8 + 32 + 32 + 32 + 8 + 8 + 1 + 1 + 32 + 32 + 1 + 8 + 8 + 1, // Adjust space as needed
+        space = SmartVault::space(&vault_params.name, 0),
         seeds = [b"smart-vault", owner.key().as_ref(), collateral_mint.key().as_ref()],
         bump
     )]
@@ -55,31 +335,43 @@ pub struct InitializeSmartVault<'info> {
     )]
     pub vault_token_account: Account<'info, TokenAccount>,
     
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"vault-shares", smart_vault.key().as_ref()],
+        bump,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = smart_vault,
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+    
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+// Auto-compounding vaults accept deposits from anyone. Vaults that pay yield out to the
+// owner only accept the owner's deposits, since other depositors would not earn that yield.
 #[derive(Accounts)]
 pub struct DepositToStrategy<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub depositor: Signer<'info>,
     
     #[account(
         mut,
-        seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
         bump = smart_vault.bump,
-        has_one = owner,
-        constraint = smart_vault.active == true
+        constraint = smart_vault.active,
+        constraint = smart_vault.auto_compound || smart_vault.owner == depositor.key() @ ErrorCode::DepositorNotAllowed
     )]
     pub smart_vault: Account<'info, SmartVault>,
     
     #[account(
         mut,
-        constraint = owner_token_account.owner == owner.key(),
-        constraint = owner_token_account.mint == smart_vault.collateral_mint
+        constraint = depositor_token_account.owner == depositor.key(),
+        constraint = depositor_token_account.mint == smart_vault.collateral_mint
     )]
-    pub owner_token_account: Account<'info, TokenAccount>,
+    pub depositor_token_account: Account<'info, TokenAccount>,
     
     #[account(
         mut,
@@ -87,29 +379,306 @@ pub struct DepositToStrategy<'info> {
     )]
     pub vault_token_account: Account<'info, TokenAccount>,
     
+    #[account(
+        mut,
+        address = smart_vault.share_mint
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        associated_token::mint = share_mint,
+        associated_token::authority = depositor
+    )]
+    pub depositor_share_account: Box<Account<'info, TokenAccount>>,
+    
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    
+    pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
+// Any share holder (the owner or a fee recipient) can redeem shares for the underlying,
+// including while the vault is paused
 #[derive(Accounts)]
 pub struct WithdrawFromStrategy<'info> {
     #[account(mut)]
+    pub withdrawer: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        mut,
+        constraint = withdrawer_token_account.owner == withdrawer.key(),
+        constraint = withdrawer_token_account.mint == smart_vault.collateral_mint
+    )]
+    pub withdrawer_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = withdrawer_share_account.owner == withdrawer.key(),
+        constraint = withdrawer_share_account.mint == smart_vault.share_mint
+    )]
+    pub withdrawer_share_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        address = smart_vault.share_mint
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+// The strategy adapter for every allocation is passed as a remaining account, in allocation order,
+// followed by the adapters of any strategies the update drops
+#[derive(Accounts)]
+#[instruction(allocations: Vec<StrategyAllocation>)]
+pub struct UpdateStrategyAllocation<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        constraint = smart_vault.active,
+        realloc = SmartVault::space(&smart_vault.name, allocations.len()),
+        realloc::payer = curator,
+        realloc::zero = false
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(strategy_id: u8)]
+pub struct RegisterStrategyAdapter<'info> {
+    #[account(mut)]
+    pub curator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        has_one = collateral_mint,
+        constraint = smart_vault.active
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    pub collateral_mint: Account<'info, token::Mint>,
+    
+    // The vault's underlying, or another asset reached through a swap adapter
+    pub asset_mint: Account<'info, token::Mint>,
+    
+    #[account(
+        init,
+        payer = curator,
+        space = StrategyAdapter::SPACE,
+        seeds = [b"strategy-adapter", smart_vault.key().as_ref(), &[strategy_id]],
+        bump
+    )]
+    pub strategy_adapter: Account<'info, StrategyAdapter>,
+    
+    #[account(
+        init,
+        payer = curator,
+        seeds = [b"strategy-position", smart_vault.key().as_ref(), &[strategy_id]],
+        bump,
+        token::mint = asset_mint,
+        token::authority = smart_vault,
+    )]
+    pub position_token_account: Account<'info, TokenAccount>,
+    
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+// Strategy adapters and their position token accounts are passed as remaining
+// accounts, one (adapter, position) pair per entry in `smart_vault.strategies`.
+#[derive(Accounts)]
+pub struct RebalanceStrategies<'info> {
+    pub curator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        constraint = smart_vault.active
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+// Harvest takes the same remaining accounts layout as `RebalanceStrategies`
+#[derive(Accounts)]
+pub struct Harvest<'info> {
+    pub curator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator,
+        constraint = smart_vault.active
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    
+    #[account(
+        mut,
+        address = smart_vault.share_mint
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+    
+    #[account(
+        mut,
+        constraint = fee_recipient_share_account.owner == smart_vault.fee_recipient,
+        constraint = fee_recipient_share_account.mint == smart_vault.share_mint
+    )]
+    pub fee_recipient_share_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"yield-history", smart_vault.key().as_ref()],
+        bump = yield_history.bump,
+        has_one = smart_vault
+    )]
+    pub yield_history: Box<Account<'info, YieldHistory>>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultFees<'info> {
     pub owner: Signer<'info>,
     
     #[account(
         mut,
         seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
         bump = smart_vault.bump,
-        has_one = owner,
-        constraint = smart_vault.active == true
+        has_one = owner
     )]
     pub smart_vault: Account<'info, SmartVault>,
     
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+    
     #[account(
         mut,
-        constraint = owner_token_account.owner == owner.key(),
-        constraint = owner_token_account.mint == smart_vault.collateral_mint
+        address = smart_vault.share_mint
     )]
-    pub owner_token_account: Account<'info, TokenAccount>,
+    pub share_mint: Box<Account<'info, Mint>>,
+    
+    // Receives the fees accrued before the change
+    #[account(
+        mut,
+        constraint = fee_recipient_share_account.owner == smart_vault.fee_recipient,
+        constraint = fee_recipient_share_account.mint == smart_vault.share_mint
+    )]
+    pub fee_recipient_share_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetDepositLimits<'info> {
+    pub curator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SetVaultRoles<'info> {
+    pub owner: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = owner
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+}
+
+#[derive(Accounts)]
+pub struct DeallocateStrategy<'info> {
+    pub guardian: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = guardian
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        mut,
+        seeds = [b"strategy-adapter", smart_vault.key().as_ref(), &[strategy_adapter.strategy_id]],
+        bump = strategy_adapter.bump,
+        has_one = smart_vault,
+        has_one = position_token_account
+    )]
+    pub strategy_adapter: Account<'info, StrategyAdapter>,
+    
+    #[account(mut)]
+    pub position_token_account: Account<'info, TokenAccount>,
     
     #[account(
         mut,
@@ -121,7 +690,12 @@ pub struct WithdrawFromStrategy<'info> {
 }
 
 #[derive(Accounts)]
-pub struct UpdateStrategyAllocation<'info> {
+pub struct GetVaultRisk<'info> {
+    pub smart_vault: Account<'info, SmartVault>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimYield<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
     
@@ -129,40 +703,92 @@ pub struct UpdateStrategyAllocation<'info> {
         mut,
         seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
         bump = smart_vault.bump,
-        has_one = owner,
-        constraint = smart_vault.active == true
+        has_one = owner
     )]
     pub smart_vault: Account<'info, SmartVault>,
+    
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == owner.key(),
+        constraint = owner_token_account.mint == smart_vault.collateral_mint
+    )]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
 }
 
 // Implementation of functions
 pub fn initialize_smart_vault(ctx: Context<InitializeSmartVault>, vault_params: SmartVaultParams) -> Result<()> {
+    require!(vault_params.name.len() <= MAX_VAULT_NAME_LEN, ErrorCode::NameTooLong);
+    require!(
+        (MIN_RISK_LEVEL..=MAX_RISK_LEVEL).contains(&vault_params.risk_level),
+        ErrorCode::InvalidRiskLevel
+    );
+    
     let smart_vault = &mut ctx.accounts.smart_vault;
     
     smart_vault.owner = ctx.accounts.owner.key();
+    smart_vault.curator = ctx.accounts.owner.key();
+    smart_vault.guardian = ctx.accounts.owner.key();
     smart_vault.collateral_mint = ctx.accounts.collateral_mint.key();
     smart_vault.vault_token_account = ctx.accounts.vault_token_account.key();
+    smart_vault.share_mint = ctx.accounts.share_mint.key();
     smart_vault.total_deposited = 0;
     smart_vault.total_allocated = 0;
     smart_vault.risk_level = vault_params.risk_level;
+    smart_vault.risk_score = 0;
     smart_vault.auto_compound = vault_params.auto_compound;
     smart_vault.name = vault_params.name;
     smart_vault.strategies = Vec::new();
     smart_vault.active = true;
     smart_vault.last_update_time = Clock::get()?.unix_timestamp;
     smart_vault.total_yield_earned = 0;
-    // Set a fixed bump value for now until we resolve the build issues
-    smart_vault.bump = 255; // We'll update this later
+    smart_vault.claimable_yield = 0;
+    smart_vault.fee_recipient = ctx.accounts.owner.key();
+    smart_vault.management_fee_bps = 0;
+    smart_vault.performance_fee_bps = 0;
+    smart_vault.high_water_mark = PRICE_PER_SHARE_SCALE;
+    smart_vault.last_fee_time = smart_vault.last_update_time;
+    smart_vault.queued_withdrawals = 0;
+    smart_vault.reserved_withdrawals = 0;
+    smart_vault.illiquid_allocated = 0;
+    smart_vault.next_withdrawal_ticket_id = 0;
+    // Uncapped until the curator sets limits; protocol limits still apply at deposit time
+    smart_vault.tvl_cap = u64::MAX;
+    smart_vault.user_cap = u64::MAX;
+    smart_vault.min_deposit = 0;
+    smart_vault.strategy_adapter_count = 0;
+    smart_vault.bump = ctx.bumps.smart_vault;
     
     Ok(())
 }
 
 pub fn deposit_to_strategy(ctx: Context<DepositToStrategy>, amount: u64) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let global_config = &ctx.accounts.global_config;
+    let share_supply = ctx.accounts.share_mint.supply;
+    
+    let existing_position = if share_supply == 0 {
+        0
+    } else {
+        mul_div(ctx.accounts.depositor_share_account.amount, smart_vault.total_deposited, share_supply)?
+    };
+    smart_vault.check_deposit_limits(amount, existing_position, global_config)?;
+    
+    let shares = smart_vault.shares_for_deposit(amount, share_supply)?;
+    require!(shares > 0, ErrorCode::DepositBelowMinimum);
+    
     // Transfer tokens from user to vault
     let transfer_instruction = Transfer {
-        from: ctx.accounts.owner_token_account.to_account_info(),
+        from: ctx.accounts.depositor_token_account.to_account_info(),
         to: ctx.accounts.vault_token_account.to_account_info(),
-        authority: ctx.accounts.owner.to_account_info(),
+        authority: ctx.accounts.depositor.to_account_info(),
     };
     
     token::transfer(
@@ -173,25 +799,67 @@ pub fn deposit_to_strategy(ctx: Context<DepositToStrategy>, amount: u64) -> Resu
         amount,
     )?;
     
+    // Mint vault shares to the depositor
+    let smart_vault = &ctx.accounts.smart_vault;
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+    
+    token::mint_to(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.share_mint.to_account_info(),
+                to: ctx.accounts.depositor_share_account.to_account_info(),
+                authority: smart_vault.to_account_info(),
+            },
+            signer,
+        ),
+        shares,
+    )?;
+    
     // Update smart vault state
     let smart_vault = &mut ctx.accounts.smart_vault;
-    smart_vault.total_deposited = smart_vault.total_deposited.checked_add(amount).unwrap();
+    smart_vault.total_deposited = smart_vault.total_deposited.checked_add(amount).ok_or(ErrorCode::ArithmeticOverflow)?;
     smart_vault.last_update_time = Clock::get()?.unix_timestamp;
     
-    // Here we would allocate to strategies based on risk level and strategy allocations
-    // For demo purposes, we're just tracking the deposit
+    // Deposits stay idle until the curator rebalances them into strategies
     
     Ok(())
 }
 
 pub fn withdraw_from_strategy(ctx: Context<WithdrawFromStrategy>, amount: u64) -> Result<()> {
     let smart_vault = &mut ctx.accounts.smart_vault;
-    require!(amount <= ctx.accounts.vault_token_account.amount, ErrorCode::InsufficientFunds);
+    require!(amount <= smart_vault.total_deposited, ErrorCode::InsufficientFunds);
+    
+    // Funds deployed in strategies have to go through a withdrawal ticket instead
+    let idle_balance = smart_vault.free_idle_balance(&ctx.accounts.vault_token_account);
+    require!(amount <= idle_balance, ErrorCode::InsufficientIdleLiquidity);
+    
+    let shares = smart_vault.shares_for_withdrawal(amount, ctx.accounts.share_mint.supply)?;
+    require!(shares <= ctx.accounts.withdrawer_share_account.amount, ErrorCode::InsufficientShares);
+    
+    // Burn the redeemed shares
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.share_mint.to_account_info(),
+                from: ctx.accounts.withdrawer_share_account.to_account_info(),
+                authority: ctx.accounts.withdrawer.to_account_info(),
+            },
+        ),
+        shares,
+    )?;
     
     // Calculate vault signer seeds
     let seeds = &[
         b"smart-vault",
-        ctx.accounts.owner.key.as_ref(),
+        smart_vault.owner.as_ref(),
         smart_vault.collateral_mint.as_ref(),
         &[smart_vault.bump],
     ];
@@ -200,7 +868,7 @@ pub fn withdraw_from_strategy(ctx: Context<WithdrawFromStrategy>, amount: u64) -
     // Transfer tokens from vault to user
     let transfer_instruction = Transfer {
         from: ctx.accounts.vault_token_account.to_account_info(),
-        to: ctx.accounts.owner_token_account.to_account_info(),
+        to: ctx.accounts.withdrawer_token_account.to_account_info(),
         authority: smart_vault.to_account_info(),
     };
     
@@ -214,20 +882,53 @@ pub fn withdraw_from_strategy(ctx: Context<WithdrawFromStrategy>, amount: u64) -
     )?;
     
     // Update smart vault state
-    smart_vault.total_deposited = smart_vault.total_deposited.checked_sub(amount).unwrap();
+    smart_vault.withdraw_deposits(amount)?;
     smart_vault.last_update_time = Clock::get()?.unix_timestamp;
     
     Ok(())
 }
 
-pub fn update_strategy_allocation(ctx: Context<UpdateStrategyAllocation>, allocations: Vec<StrategyAllocation>) -> Result<()> {
+pub fn update_strategy_allocation<'info>(
+    ctx: Context<'_, '_, 'info, 'info, UpdateStrategyAllocation<'info>>,
+    allocations: Vec<StrategyAllocation>,
+) -> Result<()> {
     let smart_vault = &mut ctx.accounts.smart_vault;
     
-    // Validate total allocation is 100%
-    let total_allocation: u8 = allocations.iter().map(|a| a.allocation_percentage).sum();
-    require!(total_allocation == 100, ErrorCode::InvalidAllocation);
+    require!(allocations.len() <= MAX_STRATEGIES, ErrorCode::TooManyStrategies);
+    
+    let dropped: Vec<u8> = smart_vault
+        .strategies
+        .iter()
+        .map(|strategy| strategy.strategy_id)
+        .filter(|strategy_id| allocations.iter().all(|allocation| allocation.strategy_id != *strategy_id))
+        .collect();
+    require!(
+        ctx.remaining_accounts.len() == allocations.len() + dropped.len(),
+        ErrorCode::InvalidStrategyAccounts
+    );
+    
+    validate_allocations(&allocations)?;
+    
+    // No strategy may be riskier than the vault allows
+    let mut risk_tiers = Vec::with_capacity(allocations.len());
+    for (allocation, account) in allocations.iter().zip(ctx.remaining_accounts.iter()) {
+        let adapter = Account::<StrategyAdapter>::try_from(account)?;
+        require!(adapter.smart_vault == smart_vault.key(), ErrorCode::InvalidStrategyAccounts);
+        require!(adapter.strategy_id == allocation.strategy_id, ErrorCode::InvalidStrategyAccounts);
+        require!(adapter.risk_tier <= smart_vault.risk_level, ErrorCode::StrategyRiskTooHigh);
+        risk_tiers.push(adapter.risk_tier);
+    }
+    
+    // Funds left in a dropped strategy would no longer be reachable by rebalancing or unwinding
+    for (strategy_id, account) in dropped.iter().zip(ctx.remaining_accounts[allocations.len()..].iter()) {
+        let adapter = Account::<StrategyAdapter>::try_from(account)?;
+        require!(adapter.smart_vault == smart_vault.key(), ErrorCode::InvalidStrategyAccounts);
+        require!(adapter.strategy_id == *strategy_id, ErrorCode::InvalidStrategyAccounts);
+        require!(adapter.deployed_amount == 0, ErrorCode::StrategyStillDeployed);
+    }
     
     // Update strategy allocations
+    smart_vault.risk_score = compute_risk_score(&allocations, &risk_tiers);
     smart_vault.strategies = allocations;
     smart_vault.last_update_time = Clock::get()?.unix_timestamp;
     
@@ -237,10 +938,892 @@ pub fn update_strategy_allocation(ctx: Context<UpdateStrategyAllocation>, alloca
     Ok(())
 }
 
+pub fn register_strategy_adapter(ctx: Context<RegisterStrategyAdapter>, strategy_id: u8, risk_tier: u8) -> Result<()> {
+    require!((MIN_RISK_LEVEL..=MAX_RISK_LEVEL).contains(&risk_tier), ErrorCode::InvalidRiskLevel);
+    
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    
+    strategy_adapter.smart_vault = ctx.accounts.smart_vault.key();
+    strategy_adapter.strategy_id = strategy_id;
+    strategy_adapter.risk_tier = risk_tier;
+    strategy_adapter.asset_mint = ctx.accounts.asset_mint.key();
+    strategy_adapter.position_token_account = ctx.accounts.position_token_account.key();
+    strategy_adapter.deployed_amount = 0;
+    strategy_adapter.external_amount = 0;
+    strategy_adapter.reported_value = 0;
+    strategy_adapter.last_report_time = Clock::get()?.unix_timestamp;
+    strategy_adapter.bump = ctx.bumps.strategy_adapter;
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.strategy_adapter_count = smart_vault.strategy_adapter_count.checked_add(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    Ok(())
+}
+
+pub fn rebalance_strategies<'info>(ctx: Context<'_, '_, 'info, 'info, RebalanceStrategies<'info>>) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let mut positions = load_strategy_positions(smart_vault, ctx.remaining_accounts)?;
+    
+    let targets = smart_vault
+        .strategies
+        .iter()
+        .map(|allocation| apply_bps(smart_vault.total_deposited, allocation.allocation_bps))
+        .collect::<Result<Vec<u64>>>()?;
+    
+    // Pull funds out of over-allocated strategies first so the idle balance can fund the rest.
+    // Funds held externally stay deployed until the strategy returns them, and strategies
+    // in other assets are rebalanced with `swap_into_strategy`/`swap_out_of_strategy`.
+    for ((adapter, position), target) in positions.iter_mut().zip(targets.iter()) {
+        if !adapter.holds_vault_underlying(smart_vault) {
+            continue;
+        }
+        if adapter.deployed_amount > *target {
+            let excess = (adapter.deployed_amount - *target).min(position.amount);
+            if excess > 0 {
+                transfer_from_vault(
+                    smart_vault,
+                    position,
+                    &ctx.accounts.vault_token_account,
+                    &ctx.accounts.token_program,
+                    excess,
+                )?;
+            }
+            adapter.deployed_amount -= excess;
+        }
+    }
+    
+    for ((adapter, position), target) in positions.iter_mut().zip(targets.iter()) {
+        if !adapter.holds_vault_underlying(smart_vault) {
+            continue;
+        }
+        if adapter.deployed_amount < *target {
+            let shortfall = *target - adapter.deployed_amount;
+            transfer_from_vault(
+                smart_vault,
+                &ctx.accounts.vault_token_account,
+                position,
+                &ctx.accounts.token_program,
+                shortfall,
+            )?;
+            adapter.deployed_amount = *target;
+        }
+    }
+    
+    let total_allocated = positions
+        .iter()
+        .try_fold(0u64, |total, (adapter, _)| total.checked_add(adapter.deployed_amount))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    for (adapter, _) in positions.iter() {
+        adapter.exit(&crate::ID)?;
+    }
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.total_allocated = total_allocated;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+    
+    Ok(())
+}
+
+pub fn harvest<'info>(ctx: Context<'_, '_, 'info, 'info, Harvest<'info>>) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let current_time = Clock::get()?.unix_timestamp;
+    let mut positions = load_strategy_positions(smart_vault, ctx.remaining_accounts)?;
+    
+    // Book everything above the deployed principal as realized yield, and anything below it
+    // as a realized loss written off the principal. Gains and losses on strategies in other
+    // assets are realized when they are swapped back out.
+    let mut yields = Vec::with_capacity(positions.len());
+    let mut total_loss = 0u64;
+    for (adapter, position) in positions.iter_mut() {
+        if !adapter.holds_vault_underlying(smart_vault) {
+            yields.push(0);
+            continue;
+        }
+        let reported_value = adapter.current_value(position);
+        yields.push(reported_value.saturating_sub(adapter.deployed_amount));
+        if reported_value < adapter.deployed_amount {
+            total_loss = total_loss.checked_add(adapter.deployed_amount - reported_value).ok_or(ErrorCode::ArithmeticOverflow)?;
+            adapter.deployed_amount = reported_value;
+        }
+        adapter.reported_value = reported_value;
+        adapter.last_report_time = current_time;
+    }
+    
+    let total_yield = yields
+        .iter()
+        .try_fold(0u64, |total, amount| total.checked_add(*amount))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    // With auto-compound each strategy keeps its share of the yield per the allocation,
+    // otherwise the whole yield is swept back to the vault token account. Shares of
+    // strategies in other assets stay idle until the curator swaps them in.
+    let compound_shares = if smart_vault.auto_compound {
+        smart_vault
+            .strategies
+            .iter()
+            .zip(positions.iter())
+            .map(|(allocation, (adapter, _))| {
+                if adapter.holds_vault_underlying(smart_vault) {
+                    apply_bps(total_yield, allocation.allocation_bps)
+                } else {
+                    Ok(0)
+                }
+            })
+            .collect::<Result<Vec<u64>>>()?
+    } else {
+        vec![0; positions.len()]
+    };
+    
+    for ((adapter, position), (earned, share)) in positions.iter_mut().zip(yields.iter().zip(compound_shares.iter())) {
+        if earned > share {
+            transfer_from_vault(
+                smart_vault,
+                position,
+                &ctx.accounts.vault_token_account,
+                &ctx.accounts.token_program,
+                earned - share,
+            )?;
+            adapter.reported_value -= earned - share;
+        }
+    }
+    
+    for ((adapter, position), (earned, share)) in positions.iter_mut().zip(yields.iter().zip(compound_shares.iter())) {
+        if share > earned {
+            transfer_from_vault(
+                smart_vault,
+                &ctx.accounts.vault_token_account,
+                position,
+                &ctx.accounts.token_program,
+                share - earned,
+            )?;
+            adapter.reported_value = adapter.reported_value.checked_add(share - earned).ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+        adapter.deployed_amount = adapter.deployed_amount.checked_add(*share).ok_or(ErrorCode::ArithmeticOverflow)?;
+    }
+    
+    let compounded = compound_shares
+        .iter()
+        .try_fold(0u64, |total, amount| total.checked_add(*amount))
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    for (adapter, _) in positions.iter() {
+        adapter.exit(&crate::ID)?;
+    }
+    
+    let global_config = &ctx.accounts.global_config;
+    let share_supply = ctx.accounts.share_mint.supply;
+    let management_fee_bps = smart_vault.management_fee_bps.min(global_config.max_management_fee_bps);
+    let performance_fee_bps = smart_vault.performance_fee_bps.min(global_config.max_performance_fee_bps);
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    // Losses lower the share price, which then has to recover past the high-water mark
+    // before performance fees are charged again
+    smart_vault.total_allocated = smart_vault.total_allocated.saturating_sub(total_loss);
+    smart_vault.total_deposited = smart_vault.total_deposited.saturating_sub(total_loss);
+    smart_vault.total_yield_earned = smart_vault.total_yield_earned.checked_add(total_yield).ok_or(ErrorCode::ArithmeticOverflow)?;
+    let mut distributed = 0;
+    let performance_fee = if smart_vault.auto_compound {
+        // Compounded yield becomes principal; rounding dust stays idle in the vault
+        smart_vault.total_deposited = smart_vault.total_deposited.checked_add(total_yield).ok_or(ErrorCode::ArithmeticOverflow)?;
+        smart_vault.total_allocated = smart_vault.total_allocated.checked_add(compounded).ok_or(ErrorCode::ArithmeticOverflow)?;
+        
+        let price_per_share = smart_vault.price_per_share(share_supply)?;
+        let gain_per_share = price_per_share.saturating_sub(smart_vault.high_water_mark);
+        apply_bps(mul_div(gain_per_share, share_supply, PRICE_PER_SHARE_SCALE)?, performance_fee_bps)?
+    } else {
+        // Yield is paid out instead of raising the share price, so the performance fee is
+        // held back from it and stays in the vault to back the fee shares
+        let fee = apply_bps(total_yield, performance_fee_bps)?;
+        distributed = total_yield - fee;
+        smart_vault.claimable_yield = smart_vault.claimable_yield.checked_add(distributed).ok_or(ErrorCode::ArithmeticOverflow)?;
+        smart_vault.total_deposited = smart_vault.total_deposited.checked_add(fee).ok_or(ErrorCode::ArithmeticOverflow)?;
+        fee
+    };
+    
+    let management_fee = smart_vault.accrued_management_fee(management_fee_bps, current_time)?;
+    let fee_shares = smart_vault.fee_shares(performance_fee.saturating_add(management_fee), share_supply)?;
+    let share_supply = share_supply.checked_add(fee_shares).ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    let price_per_share = smart_vault.price_per_share(share_supply)?;
+    smart_vault.high_water_mark = smart_vault.high_water_mark.max(price_per_share);
+    smart_vault.last_fee_time = current_time;
+    smart_vault.last_update_time = current_time;
+    
+    ctx.accounts.yield_history.record(current_time, price_per_share, distributed, share_supply)?;
+    
+    mint_fee_shares(
+        &ctx.accounts.smart_vault,
+        &ctx.accounts.share_mint,
+        &ctx.accounts.fee_recipient_share_account,
+        &ctx.accounts.token_program,
+        fee_shares,
+    )?;
+    
+    msg!("Harvested yield: {}, loss: {}, fee shares minted: {}", total_yield, total_loss, fee_shares);
+    Ok(())
+}
+
+pub fn set_vault_fees(
+    ctx: Context<SetVaultFees>,
+    management_fee_bps: u16,
+    performance_fee_bps: u16,
+    fee_recipient: Pubkey,
+) -> Result<()> {
+    let global_config = &ctx.accounts.global_config;
+    require!(management_fee_bps <= global_config.max_management_fee_bps, ErrorCode::FeeAboveProtocolMaximum);
+    require!(performance_fee_bps <= global_config.max_performance_fee_bps, ErrorCode::FeeAboveProtocolMaximum);
+    
+    // Management fees up to now are taken at the old rate, to the old recipient
+    let current_time = Clock::get()?.unix_timestamp;
+    let old_management_fee_bps = ctx.accounts.smart_vault.management_fee_bps.min(global_config.max_management_fee_bps);
+    let management_fee = ctx.accounts.smart_vault.accrued_management_fee(old_management_fee_bps, current_time)?;
+    let fee_shares = ctx.accounts.smart_vault.fee_shares(management_fee, ctx.accounts.share_mint.supply)?;
+    mint_fee_shares(
+        &ctx.accounts.smart_vault,
+        &ctx.accounts.share_mint,
+        &ctx.accounts.fee_recipient_share_account,
+        &ctx.accounts.token_program,
+        fee_shares,
+    )?;
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.management_fee_bps = management_fee_bps;
+    smart_vault.performance_fee_bps = performance_fee_bps;
+    smart_vault.fee_recipient = fee_recipient;
+    smart_vault.last_fee_time = current_time;
+    smart_vault.last_update_time = current_time;
+    
+    Ok(())
+}
+
+pub fn set_deposit_limits(ctx: Context<SetDepositLimits>, tvl_cap: u64, user_cap: u64, min_deposit: u64) -> Result<()> {
+    validate_deposit_limits(&ctx.accounts.global_config, tvl_cap, user_cap, min_deposit)?;
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.tvl_cap = tvl_cap;
+    smart_vault.user_cap = user_cap;
+    smart_vault.min_deposit = min_deposit;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+    
+    Ok(())
+}
+
+pub fn set_vault_roles(ctx: Context<SetVaultRoles>, curator: Pubkey, guardian: Pubkey) -> Result<()> {
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    
+    smart_vault.curator = curator;
+    smart_vault.guardian = guardian;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+    
+    Ok(())
+}
+
+// Pulls a strategy's principal back to the vault and zeroes its allocation. The remaining
+// allocations then sum to less than 100% and the difference stays idle until the curator
+// sets a new allocation. The stored risk score is left as is until then, which overstates
+// rather than understates the vault's risk.
+// Strategies in other assets only stop receiving funds; the curator swaps them out
+pub fn deallocate_strategy(ctx: Context<DeallocateStrategy>) -> Result<()> {
+    let strategy_adapter = &ctx.accounts.strategy_adapter;
+    let strategy_id = strategy_adapter.strategy_id;
+    let withdrawn = if strategy_adapter.holds_vault_underlying(&ctx.accounts.smart_vault) {
+        strategy_adapter.deployed_amount.min(ctx.accounts.position_token_account.amount)
+    } else {
+        0
+    };
+    
+    if withdrawn > 0 {
+        transfer_from_vault(
+            &ctx.accounts.smart_vault,
+            &ctx.accounts.position_token_account,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.token_program,
+            withdrawn,
+        )?;
+    }
+    
+    let strategy_adapter = &mut ctx.accounts.strategy_adapter;
+    strategy_adapter.deployed_amount -= withdrawn;
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.total_allocated = smart_vault.total_allocated.saturating_sub(withdrawn);
+    if let Some(allocation) = smart_vault.strategies.iter_mut().find(|allocation| allocation.strategy_id == strategy_id) {
+        allocation.allocation_bps = 0;
+    }
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+    
+    msg!("Strategy {} deallocated, {} returned to the vault", strategy_id, withdrawn);
+    Ok(())
+}
+
+pub fn get_vault_risk(ctx: Context<GetVaultRisk>) -> Result<VaultRiskReport> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    
+    Ok(VaultRiskReport {
+        risk_level: smart_vault.risk_level,
+        risk_score: smart_vault.risk_score,
+        profile: RiskProfile::from_score(smart_vault.risk_score),
+    })
+}
+
+pub fn claim_yield(ctx: Context<ClaimYield>) -> Result<()> {
+    let amount = ctx.accounts.smart_vault.claimable_yield;
+    require!(amount > 0, ErrorCode::NoYieldToClaim);
+    
+    transfer_from_vault(
+        &ctx.accounts.smart_vault,
+        &ctx.accounts.vault_token_account,
+        &ctx.accounts.owner_token_account,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+    
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.claimable_yield = 0;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+    
+    Ok(())
+}
+
+// Helper functions
+
+// Allocation-weighted strategy tier plus a penalty proportional to the allocation's
+// Herfindahl index, so a vault in a single strategy scores higher than a diversified one
+fn compute_risk_score(allocations: &[StrategyAllocation], risk_tiers: &[u8]) -> u16 {
+    let max_bps = MAX_BPS as u64;
+    let weighted_tier = allocations
+        .iter()
+        .zip(risk_tiers.iter())
+        .map(|(allocation, tier)| allocation.allocation_bps as u64 * *tier as u64)
+        .sum::<u64>()
+        * RISK_SCORE_SCALE
+        / max_bps;
+    let concentration = allocations
+        .iter()
+        .map(|allocation| (allocation.allocation_bps as u64).pow(2))
+        .sum::<u64>()
+        / max_bps;
+    let penalty = concentration * MAX_CONCENTRATION_PENALTY / max_bps;
+    
+    (weighted_tier + penalty) as u16
+}
+
+pub(crate) type StrategyPosition<'info> = (Account<'info, StrategyAdapter>, Account<'info, TokenAccount>);
+
+// Deserializes the (adapter, position) pairs for every strategy in the vault's allocation,
+// in allocation order
+pub(crate) fn load_strategy_positions<'info>(
+    smart_vault: &Account<'info, SmartVault>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+) -> Result<Vec<StrategyPosition<'info>>> {
+    require!(
+        remaining_accounts.len() == smart_vault.strategies.len() * 2,
+        ErrorCode::InvalidStrategyAccounts
+    );
+    
+    smart_vault
+        .strategies
+        .iter()
+        .zip(remaining_accounts.chunks(2))
+        .map(|(allocation, accounts)| {
+            let adapter = Account::<StrategyAdapter>::try_from(&accounts[0])?;
+            let position = Account::<TokenAccount>::try_from(&accounts[1])?;
+            
+            require!(adapter.smart_vault == smart_vault.key(), ErrorCode::InvalidStrategyAccounts);
+            require!(adapter.strategy_id == allocation.strategy_id, ErrorCode::InvalidStrategyAccounts);
+            require!(position.key() == adapter.position_token_account, ErrorCode::InvalidStrategyAccounts);
+            
+            Ok((adapter, position))
+        })
+        .collect()
+}
+
+// Pulls up to `amount` of deployed principal back into the vault token account, draining
+// strategies in allocation order. Returns the amount actually unwound.
+pub(crate) fn unwind_strategies<'info>(
+    smart_vault: &mut Account<'info, SmartVault>,
+    positions: &mut [StrategyPosition<'info>],
+    vault_token_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<u64> {
+    let mut remaining = amount;
+    
+    for (adapter, position) in positions.iter_mut() {
+        if remaining == 0 {
+            break;
+        }
+        if !adapter.holds_vault_underlying(smart_vault) {
+            continue;
+        }
+        
+        let withdrawn = remaining.min(adapter.deployed_amount).min(position.amount);
+        if withdrawn == 0 {
+            continue;
+        }
+        
+        transfer_from_vault(smart_vault, position, vault_token_account, token_program, withdrawn)?;
+        adapter.deployed_amount -= withdrawn;
+        adapter.exit(&crate::ID)?;
+        remaining -= withdrawn;
+    }
+    
+    let unwound = amount - remaining;
+    smart_vault.total_allocated = smart_vault.total_allocated.saturating_sub(unwound);
+    
+    Ok(unwound)
+}
+
+// Limits must fit within the protocol's, and each must leave room for the next
+fn validate_deposit_limits(global_config: &GlobalConfig, tvl_cap: u64, user_cap: u64, min_deposit: u64) -> Result<()> {
+    require!(tvl_cap <= global_config.max_vault_tvl, ErrorCode::InvalidDepositLimits);
+    require!(user_cap <= tvl_cap, ErrorCode::InvalidDepositLimits);
+    require!(min_deposit >= global_config.min_deposit_floor, ErrorCode::InvalidDepositLimits);
+    require!(min_deposit <= user_cap, ErrorCode::InvalidDepositLimits);
+    
+    Ok(())
+}
+
+// Each strategy may only appear once, and the allocations must sum to 100%
+fn validate_allocations(allocations: &[StrategyAllocation]) -> Result<()> {
+    for (index, allocation) in allocations.iter().enumerate() {
+        require!(
+            allocations[..index].iter().all(|other| other.strategy_id != allocation.strategy_id),
+            ErrorCode::DuplicateStrategy
+        );
+    }
+    
+    let total_allocation: u32 = allocations.iter().map(|a| a.allocation_bps as u32).sum();
+    require!(total_allocation == MAX_BPS as u32, ErrorCode::InvalidAllocation);
+    
+    Ok(())
+}
+
+pub(crate) fn mul_div(amount: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    let result = (amount as u128)
+        .checked_mul(numerator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?
+        .checked_div(denominator as u128)
+        .ok_or(ErrorCode::ArithmeticOverflow)?;
+    
+    u64::try_from(result).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+}
+
+fn apply_bps(amount: u64, bps: u16) -> Result<u64> {
+    mul_div(amount, bps as u64, MAX_BPS as u64)
+}
+
+fn mint_fee_shares<'info>(
+    smart_vault: &Account<'info, SmartVault>,
+    share_mint: &Account<'info, Mint>,
+    fee_recipient_share_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    fee_shares: u64,
+) -> Result<()> {
+    if fee_shares == 0 {
+        return Ok(());
+    }
+    
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+    
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            MintTo {
+                mint: share_mint.to_account_info(),
+                to: fee_recipient_share_account.to_account_info(),
+                authority: smart_vault.to_account_info(),
+            },
+            signer,
+        ),
+        fee_shares,
+    )
+}
+
+// Moves tokens out of an account owned by the smart vault PDA
+pub(crate) fn transfer_from_vault<'info>(
+    smart_vault: &Account<'info, SmartVault>,
+    from: &Account<'info, TokenAccount>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+    
+    let transfer_instruction = Transfer {
+        from: from.to_account_info(),
+        to: to.to_account_info(),
+        authority: smart_vault.to_account_info(),
+    };
+    
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            transfer_instruction,
+            signer,
+        ),
+        amount,
+    )
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Insufficient funds for transaction")]
     InsufficientFunds,
     #[msg("Invalid allocation percentages, must total 100%")]
     InvalidAllocation,
-} 
\ No newline at end of file
+    #[msg("Too many strategies in allocation")]
+    TooManyStrategies,
+    #[msg("Strategy appears more than once in allocation")]
+    DuplicateStrategy,
+    #[msg("Vault name is too long")]
+    NameTooLong,
+    #[msg("Risk level must be between 1 and 5")]
+    InvalidRiskLevel,
+    #[msg("Strategy risk tier exceeds the vault's risk level")]
+    StrategyRiskTooHigh,
+    #[msg("Price feed does not match the vault's underlying mint")]
+    InvalidPriceFeed,
+    #[msg("Deposit is below the vault's minimum")]
+    DepositBelowMinimum,
+    #[msg("Deposit would exceed the vault's TVL cap")]
+    VaultCapExceeded,
+    #[msg("Deposit would exceed the per-user cap")]
+    UserCapExceeded,
+    #[msg("Deposit limits are outside the protocol limits or inconsistent")]
+    InvalidDepositLimits,
+    #[msg("Only the owner can deposit into a vault that pays yield out")]
+    DepositorNotAllowed,
+    #[msg("Strategy adapter accounts do not match the vault's allocations")]
+    InvalidStrategyAccounts,
+    #[msg("Strategy still has funds deployed, rebalance it to a zero allocation before removing it")]
+    StrategyStillDeployed,
+    #[msg("No harvested yield to claim")]
+    NoYieldToClaim,
+    #[msg("Not enough vault shares for this withdrawal")]
+    InsufficientShares,
+    #[msg("Not enough idle liquidity, request a withdrawal ticket instead")]
+    InsufficientIdleLiquidity,
+    #[msg("Fee exceeds the protocol maximum")]
+    FeeAboveProtocolMaximum,
+    #[msg("Vault shares are outstanding with no deposits backing them")]
+    UnbackedShares,
+    #[msg("Withdrawal exceeds the deposits that are not held in illiquid strategies")]
+    WithdrawalExceedsLiquidity,
+    #[msg("Illiquid strategies may not hold more than the deposits left after queued withdrawals")]
+    IlliquidAllocationExceeded,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn smart_vault(total_deposited: u64) -> SmartVault {
+        SmartVault {
+            owner: Pubkey::new_unique(),
+            curator: Pubkey::new_unique(),
+            guardian: Pubkey::new_unique(),
+            collateral_mint: Pubkey::new_unique(),
+            vault_token_account: Pubkey::new_unique(),
+            share_mint: Pubkey::new_unique(),
+            total_deposited,
+            total_allocated: 0,
+            risk_level: 3,
+            risk_score: 0,
+            auto_compound: true,
+            name: String::from("Test"),
+            strategies: Vec::new(),
+            active: true,
+            last_update_time: 0,
+            total_yield_earned: 0,
+            claimable_yield: 0,
+            fee_recipient: Pubkey::new_unique(),
+            management_fee_bps: 0,
+            performance_fee_bps: 0,
+            high_water_mark: PRICE_PER_SHARE_SCALE,
+            last_fee_time: 0,
+            queued_withdrawals: 0,
+            reserved_withdrawals: 0,
+            illiquid_allocated: 0,
+            next_withdrawal_ticket_id: 0,
+            tvl_cap: u64::MAX,
+            user_cap: u64::MAX,
+            min_deposit: 0,
+            strategy_adapter_count: 0,
+            bump: 255,
+        }
+    }
+    
+    // Vaults up to 1,000,000 base units, with deposits of at least 100
+    fn global_config() -> GlobalConfig {
+        GlobalConfig {
+            authority: Pubkey::new_unique(),
+            max_management_fee_bps: 200,
+            max_performance_fee_bps: 2_000,
+            max_withdrawal_delay: 7 * 86_400,
+            max_vault_tvl: 1_000_000,
+            min_deposit_floor: 100,
+            jito_vault_program: Pubkey::new_unique(),
+            bump: 255,
+        }
+    }
+    
+    fn allocations(allocation_bps: &[u16]) -> Vec<StrategyAllocation> {
+        allocation_bps
+            .iter()
+            .enumerate()
+            .map(|(strategy_id, &allocation_bps)| StrategyAllocation {
+                strategy_id: strategy_id as u8,
+                allocation_bps,
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn allocations_must_sum_to_one_hundred_percent() {
+        assert!(validate_allocations(&allocations(&[10_000])).is_ok());
+        assert!(validate_allocations(&allocations(&[2_500, 2_500, 5_000])).is_ok());
+        
+        assert_eq!(
+            validate_allocations(&allocations(&[5_000, 4_999])).unwrap_err(),
+            ErrorCode::InvalidAllocation.into()
+        );
+        assert_eq!(
+            validate_allocations(&allocations(&[5_000, 5_001])).unwrap_err(),
+            ErrorCode::InvalidAllocation.into()
+        );
+        assert_eq!(validate_allocations(&[]).unwrap_err(), ErrorCode::InvalidAllocation.into());
+    }
+    
+    #[test]
+    fn allocations_are_summed_without_overflowing_bps() {
+        // Each entry fits in u16 but the sum does not
+        assert_eq!(
+            validate_allocations(&allocations(&[60_000, 10_000])).unwrap_err(),
+            ErrorCode::InvalidAllocation.into()
+        );
+    }
+    
+    #[test]
+    fn each_strategy_may_only_be_allocated_once() {
+        let mut duplicated = allocations(&[5_000, 5_000]);
+        duplicated[1].strategy_id = duplicated[0].strategy_id;
+        
+        assert_eq!(validate_allocations(&duplicated).unwrap_err(), ErrorCode::DuplicateStrategy.into());
+    }
+    
+    #[test]
+    fn risk_score_is_zero_without_allocations() {
+        assert_eq!(compute_risk_score(&[], &[]), 0);
+    }
+    
+    #[test]
+    fn risk_score_penalizes_concentration() {
+        // Tier 3 in one strategy, then split evenly across two and four
+        assert_eq!(compute_risk_score(&allocations(&[10_000]), &[3]), 350);
+        assert_eq!(compute_risk_score(&allocations(&[5_000, 5_000]), &[3, 3]), 325);
+        assert_eq!(compute_risk_score(&allocations(&[2_500, 2_500, 2_500, 2_500]), &[3, 3, 3, 3]), 312);
+    }
+    
+    #[test]
+    fn risk_score_weights_tiers_by_allocation() {
+        // Tier 2.5 on average, with a Herfindahl index of 0.5
+        assert_eq!(compute_risk_score(&allocations(&[5_000, 5_000]), &[1, 4]), 275);
+        // Tier 1.2 on average, with a Herfindahl index of 0.68
+        assert_eq!(compute_risk_score(&allocations(&[8_000, 2_000]), &[1, 2]), 154);
+    }
+    
+    #[test]
+    fn risk_score_maps_to_a_profile() {
+        let profile = |allocation_bps: &[u16], risk_tiers: &[u8]| {
+            RiskProfile::from_score(compute_risk_score(&allocations(allocation_bps), risk_tiers))
+        };
+        
+        assert!(matches!(profile(&[5_000, 5_000], &[1, 1]), RiskProfile::Conservative));
+        // A tier 3 vault in a single strategy sits on the moderate boundary
+        assert!(matches!(profile(&[5_000, 5_000], &[3, 3]), RiskProfile::Moderate));
+        assert!(matches!(profile(&[10_000], &[3]), RiskProfile::Moderate));
+        assert!(matches!(profile(&[10_000], &[4]), RiskProfile::Aggressive));
+    }    
+    #[test]
+    fn deposits_are_bounded_by_the_vault_and_protocol_limits() {
+        let global_config = global_config();
+        let mut vault = smart_vault(900_000);
+        vault.tvl_cap = 950_000;
+        vault.user_cap = 60_000;
+        vault.min_deposit = 1_000;
+        
+        assert!(vault.check_deposit_limits(1_000, 0, &global_config).is_ok());
+        assert!(vault.check_deposit_limits(50_000, 10_000, &global_config).is_ok());
+        
+        assert_eq!(vault.check_deposit_limits(999, 0, &global_config).unwrap_err(), ErrorCode::DepositBelowMinimum.into());
+        assert_eq!(vault.check_deposit_limits(50_001, 0, &global_config).unwrap_err(), ErrorCode::VaultCapExceeded.into());
+        assert_eq!(vault.check_deposit_limits(1_000, 59_001, &global_config).unwrap_err(), ErrorCode::UserCapExceeded.into());
+    }
+    
+    #[test]
+    fn protocol_limits_apply_to_uncapped_vaults() {
+        let global_config = global_config();
+        let vault = smart_vault(999_950);
+        
+        // The vault sets no minimum, so the protocol floor applies
+        assert_eq!(vault.check_deposit_limits(0, 0, &global_config).unwrap_err(), ErrorCode::DepositBelowMinimum.into());
+        assert_eq!(vault.check_deposit_limits(99, 0, &global_config).unwrap_err(), ErrorCode::DepositBelowMinimum.into());
+        assert_eq!(vault.check_deposit_limits(100, 0, &global_config).unwrap_err(), ErrorCode::VaultCapExceeded.into());
+        assert!(smart_vault(999_900).check_deposit_limits(100, 0, &global_config).is_ok());
+    }
+    
+    #[test]
+    fn deposit_limits_must_nest_within_the_protocol_limits() {
+        let global_config = global_config();
+        
+        assert!(validate_deposit_limits(&global_config, 1_000_000, 100_000, 100).is_ok());
+        assert!(validate_deposit_limits(&global_config, 500, 500, 500).is_ok());
+        
+        for (tvl_cap, user_cap, min_deposit) in [
+            (1_000_001, 100_000, 100), // TVL cap above the protocol maximum
+            (100_000, 100_001, 100),   // User cap above the TVL cap
+            (100_000, 10_000, 99),     // Minimum deposit below the protocol floor
+            (100_000, 10_000, 10_001), // Minimum deposit above the user cap
+        ] {
+            assert_eq!(
+                validate_deposit_limits(&global_config, tvl_cap, user_cap, min_deposit).unwrap_err(),
+                ErrorCode::InvalidDepositLimits.into()
+            );
+        }
+    }
+    
+    #[test]
+    fn management_fee_accrues_by_time() {
+        let vault = smart_vault(1_000_000_000);
+        let year = SECONDS_PER_YEAR as i64;
+        
+        assert_eq!(vault.accrued_management_fee(200, year).unwrap(), 20_000_000);
+        assert_eq!(vault.accrued_management_fee(200, year / 2).unwrap(), 10_000_000);
+        assert_eq!(vault.accrued_management_fee(0, year).unwrap(), 0);
+        assert_eq!(vault.accrued_management_fee(200, 0).unwrap(), 0);
+    }
+    
+    #[test]
+    fn management_fee_ignores_time_before_the_last_fee() {
+        let mut vault = smart_vault(1_000_000_000);
+        vault.last_fee_time = 1_000;
+        
+        assert_eq!(vault.accrued_management_fee(200, 500).unwrap(), 0);
+    }
+    
+    #[test]
+    fn fee_shares_dilute_holders_by_the_fee() {
+        let vault = smart_vault(1_000_000);
+        let share_supply = 1_000_000;
+        
+        // 10% of the vault is a ninth of the remaining holders' shares
+        let fee_shares = vault.fee_shares(100_000, share_supply).unwrap();
+        assert_eq!(fee_shares, 111_111);
+        
+        // Rounded down, so holders never lose more than the fee
+        let holders_value = mul_div(vault.total_deposited, share_supply, share_supply + fee_shares).unwrap();
+        assert!(holders_value >= 900_000);
+        assert!(holders_value - 900_000 <= 1);
+    }
+    
+    #[test]
+    fn fee_shares_are_not_minted_without_holders_or_value() {
+        let vault = smart_vault(1_000);
+        
+        assert_eq!(vault.fee_shares(100, 0).unwrap(), 0);
+        assert_eq!(vault.fee_shares(1_000, 1_000).unwrap(), 0);
+        assert_eq!(vault.fee_shares(5_000, 1_000).unwrap(), 0);
+        assert_eq!(vault.fee_shares(0, 1_000).unwrap(), 0);
+        assert_eq!(smart_vault(0).fee_shares(100, 1_000).unwrap(), 0);
+    }
+    
+    #[test]
+    fn deposits_are_minted_shares_at_the_price_per_share() {
+        assert_eq!(smart_vault(0).shares_for_deposit(500, 0).unwrap(), 500);
+        assert_eq!(smart_vault(2_000).shares_for_deposit(500, 1_000).unwrap(), 250);
+        
+        // Shares with nothing behind them would otherwise dilute the new depositor
+        assert_eq!(smart_vault(0).shares_for_deposit(500, 1_000).unwrap_err(), ErrorCode::UnbackedShares.into());
+    }
+    
+    #[test]
+    fn price_per_share_tracks_deposits_per_share() {
+        assert_eq!(smart_vault(0).price_per_share(0).unwrap(), PRICE_PER_SHARE_SCALE);
+        assert_eq!(smart_vault(1_000).price_per_share(0).unwrap(), PRICE_PER_SHARE_SCALE);
+        assert_eq!(smart_vault(1_000).price_per_share(1_000).unwrap(), PRICE_PER_SHARE_SCALE);
+        assert_eq!(smart_vault(2_000).price_per_share(1_000).unwrap(), 2 * PRICE_PER_SHARE_SCALE);
+        assert_eq!(smart_vault(1).price_per_share(3).unwrap(), 333_333_333);
+    }
+    
+    #[test]
+    fn performance_fee_leaves_holders_the_gain_net_of_the_fee() {
+        // Yield lifts the price to 1.1, and the fee is charged on the gain
+        let vault = smart_vault(1_100_000);
+        let share_supply = 1_000_000;
+        let gain_per_share = vault.price_per_share(share_supply).unwrap() - PRICE_PER_SHARE_SCALE;
+        let fee = apply_bps(mul_div(gain_per_share, share_supply, PRICE_PER_SHARE_SCALE).unwrap(), 2_000).unwrap();
+        assert_eq!(fee, 20_000);
+        
+        // Fee shares round down, so the new high-water mark is 1.08 to within one share
+        let fee_shares = vault.fee_shares(fee, share_supply).unwrap();
+        assert!(vault.price_per_share(share_supply + fee_shares).unwrap() >= 1_080_000_000);
+        assert!(vault.price_per_share(share_supply + fee_shares + 1).unwrap() < 1_080_000_000);
+    }
+    
+    #[test]
+    fn withdrawal_tickets_move_from_queued_to_reserved_to_claimed() {
+        let mut vault = smart_vault(1_000);
+        
+        vault.queue_withdrawal(300).unwrap();
+        assert_eq!((vault.total_deposited, vault.queued_withdrawals, vault.reserved_withdrawals), (700, 300, 0));
+        
+        vault.reserve_withdrawal(300).unwrap();
+        assert_eq!((vault.total_deposited, vault.queued_withdrawals, vault.reserved_withdrawals), (700, 0, 300));
+        
+        vault.release_withdrawal(300).unwrap();
+        assert_eq!((vault.total_deposited, vault.queued_withdrawals, vault.reserved_withdrawals), (700, 0, 0));
+        
+        // Tickets are only filled and claimed once
+        assert_eq!(vault.reserve_withdrawal(1).unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+        assert_eq!(vault.release_withdrawal(1).unwrap_err(), ErrorCode::ArithmeticOverflow.into());
+    }
+    
+    #[test]
+    fn illiquid_principal_leaves_queued_withdrawals_unwindable() {
+        let mut vault = smart_vault(1_000);
+        vault.add_illiquid(600).unwrap();
+        
+        // Only the 400 that unwinding can reach may be withdrawn or queued
+        assert_eq!(vault.withdrawable(), 400);
+        assert_eq!(vault.queue_withdrawal(401).unwrap_err(), ErrorCode::WithdrawalExceedsLiquidity.into());
+        vault.queue_withdrawal(250).unwrap();
+        vault.withdraw_deposits(150).unwrap();
+        assert_eq!(vault.withdraw_deposits(1).unwrap_err(), ErrorCode::WithdrawalExceedsLiquidity.into());
+        
+        // Liquid principal still covers the queue, so no more can be moved out of reach
+        assert_eq!(vault.total_deposited + vault.queued_withdrawals - vault.illiquid_allocated, vault.queued_withdrawals);
+        assert_eq!(vault.add_illiquid(1).unwrap_err(), ErrorCode::IlliquidAllocationExceeded.into());
+        
+        // Once the illiquid principal comes back it can be withdrawn again
+        vault.remove_illiquid(600);
+        assert_eq!(vault.withdrawable(), 600);
+        vault.queue_withdrawal(600).unwrap();
+        assert_eq!((vault.total_deposited, vault.queued_withdrawals), (0, 850));
+    }
+}
@@ -0,0 +1,143 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_spl::token::TokenAccount;
+
+use crate::global_config::GlobalConfig;
+
+// A swap program the protocol allows vaults to route through. Swap programs implement
+// `swap(amount_in: u64, min_amount_out: u64)` with the Anchor instruction layout and the
+// accounts (authority, source, destination, token_program) followed by any accounts of
+// their own, which callers pass through as remaining accounts.
+#[account]
+pub struct SwapAdapter {
+    pub program_id: Pubkey,
+    pub bump: u8,
+}
+
+impl SwapAdapter {
+    pub const SPACE: usize = 8 + 32 + 1;
+}
+
+#[derive(Accounts)]
+pub struct RegisterSwapAdapter<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump,
+        has_one = authority
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    /// CHECK: Any executable program can be registered by the protocol authority
+    #[account(executable)]
+    pub swap_program: UncheckedAccount<'info>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = SwapAdapter::SPACE,
+        seeds = [b"swap-adapter", swap_program.key().as_ref()],
+        bump
+    )]
+    pub swap_adapter: Account<'info, SwapAdapter>,
+
+    pub system_program: Program<'info, System>,
+}
+
+// Implementation of functions
+
+pub fn register_swap_adapter(ctx: Context<RegisterSwapAdapter>) -> Result<()> {
+    let swap_adapter = &mut ctx.accounts.swap_adapter;
+
+    swap_adapter.program_id = ctx.accounts.swap_program.key();
+    swap_adapter.bump = ctx.bumps.swap_adapter;
+
+    Ok(())
+}
+
+// Helper functions
+
+// Swaps `amount_in` from `source` into `destination` through a registered swap program.
+// The amounts actually moved are measured on the token accounts, so the slippage guard
+// holds whatever the swap program reports. Returns the amounts spent and received.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn swap_exact_in<'info>(
+    swap_program: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    source: &mut Account<'info, TokenAccount>,
+    destination: &mut Account<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+    adapter_accounts: &[AccountInfo<'info>],
+    amount_in: u64,
+    min_amount_out: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<(u64, u64)> {
+    require!(amount_in > 0, ErrorCode::InvalidSwapAmount);
+    require!(source.mint != destination.mint, ErrorCode::InvalidSwapAccounts);
+
+    let source_before = source.amount;
+    let destination_before = destination.amount;
+
+    let mut data = Vec::with_capacity(8 + 8 + 8);
+    data.extend_from_slice(&hash(b"global:swap").to_bytes()[..8]);
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(authority.key(), true),
+        AccountMeta::new(source.key(), false),
+        AccountMeta::new(destination.key(), false),
+        AccountMeta::new_readonly(token_program.key(), false),
+    ];
+    accounts.extend(adapter_accounts.iter().map(|account| {
+        if account.is_writable {
+            AccountMeta::new(account.key(), account.is_signer)
+        } else {
+            AccountMeta::new_readonly(account.key(), account.is_signer)
+        }
+    }));
+
+    let mut account_infos = vec![
+        authority.clone(),
+        source.to_account_info(),
+        destination.to_account_info(),
+        token_program.clone(),
+    ];
+    account_infos.extend(adapter_accounts.iter().cloned());
+
+    invoke_signed(
+        &Instruction {
+            program_id: swap_program.key(),
+            accounts,
+            data,
+        },
+        &account_infos,
+        signer_seeds,
+    )?;
+
+    source.reload()?;
+    destination.reload()?;
+
+    let spent = source_before.saturating_sub(source.amount);
+    let received = destination.amount.saturating_sub(destination_before);
+    require!(spent <= amount_in, ErrorCode::SwapOverspent);
+    require!(received >= min_amount_out, ErrorCode::SlippageExceeded);
+
+    Ok((spent, received))
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Swap amount must be positive")]
+    InvalidSwapAmount,
+    #[msg("Swap source and destination must hold different mints")]
+    InvalidSwapAccounts,
+    #[msg("Swap program took more than the input amount")]
+    SwapOverspent,
+    #[msg("Swap returned less than the minimum amount out")]
+    SlippageExceeded,
+}
@@ -0,0 +1,305 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount};
+
+use crate::smart_vaults::{load_strategy_positions, transfer_from_vault, SmartVault, StrategyAdapter};
+
+#[derive(Accounts)]
+pub struct PauseVault<'info> {
+    // The guardian or the owner
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        constraint = authority.key() == smart_vault.guardian || authority.key() == smart_vault.owner @ ErrorCode::Unauthorized
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+}
+
+#[derive(Accounts)]
+pub struct ResumeVault<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = owner
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+}
+
+// Strategy adapters are passed as remaining accounts, as for `rebalance_strategies`
+#[derive(Accounts)]
+pub struct EmergencyWithdrawAll<'info> {
+    // The guardian or the owner
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        constraint = authority.key() == smart_vault.guardian || authority.key() == smart_vault.owner @ ErrorCode::Unauthorized
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Only adapters already removed from the allocation, with nothing left in them
+#[derive(Accounts)]
+pub struct CloseStrategyAdapter<'info> {
+    // Registered the adapter and paid for it
+    #[account(mut)]
+    pub curator: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = curator
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        has_one = smart_vault,
+        has_one = position_token_account,
+        close = curator
+    )]
+    pub strategy_adapter: Account<'info, StrategyAdapter>,
+
+    #[account(mut)]
+    pub position_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Strategy adapters have to be closed first. The yield history is closed with the vault
+// when it was initialized.
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", owner.key().as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(address = smart_vault.share_mint)]
+    pub share_mint: Account<'info, Mint>,
+
+    /// CHECK: The vault's yield history address, which may never have been initialized
+    #[account(
+        mut,
+        seeds = [b"yield-history", smart_vault.key().as_ref()],
+        bump
+    )]
+    pub yield_history: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Implementation of functions
+
+pub fn pause_vault(ctx: Context<PauseVault>) -> Result<()> {
+    let smart_vault = &mut ctx.accounts.smart_vault;
+
+    smart_vault.active = false;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    msg!("Smart vault paused");
+    Ok(())
+}
+
+pub fn resume_vault(ctx: Context<ResumeVault>) -> Result<()> {
+    let smart_vault = &mut ctx.accounts.smart_vault;
+
+    smart_vault.active = true;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    msg!("Smart vault resumed");
+    Ok(())
+}
+
+// Pulls the full balance of every strategy position back to the vault token account,
+// regardless of allocation targets, and pauses the vault. Gains over the deployed
+// principal are booked as yield and shortfalls are realized as losses. Funds held in
+// external protocols or in other assets stay deployed and have to be unwound through
+// their adapter or swapped out.
+pub fn emergency_withdraw_all<'info>(ctx: Context<'_, '_, 'info, 'info, EmergencyWithdrawAll<'info>>) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let mut positions = load_strategy_positions(smart_vault, ctx.remaining_accounts)?;
+
+    let mut recovered: u64 = 0;
+    let mut principal: u64 = 0;
+    let mut still_external: u64 = 0;
+    for (adapter, position) in positions.iter_mut() {
+        if !adapter.holds_vault_underlying(smart_vault) {
+            still_external = still_external.checked_add(adapter.deployed_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+            continue;
+        }
+        if position.amount > 0 {
+            transfer_from_vault(
+                smart_vault,
+                position,
+                &ctx.accounts.vault_token_account,
+                &ctx.accounts.token_program,
+                position.amount,
+            )?;
+        }
+
+        recovered = recovered.checked_add(position.amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        principal = principal
+            .checked_add(adapter.deployed_amount.saturating_sub(adapter.external_amount))
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        still_external = still_external.checked_add(adapter.external_amount).ok_or(ErrorCode::ArithmeticOverflow)?;
+        adapter.deployed_amount = adapter.external_amount;
+        adapter.reported_value = adapter.external_amount;
+        adapter.exit(&crate::ID)?;
+    }
+
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    if recovered >= principal {
+        let gain = recovered - principal;
+        smart_vault.total_yield_earned = smart_vault.total_yield_earned.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        if smart_vault.auto_compound {
+            smart_vault.total_deposited = smart_vault.total_deposited.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        } else {
+            smart_vault.claimable_yield = smart_vault.claimable_yield.checked_add(gain).ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+    } else {
+        smart_vault.total_deposited = smart_vault.total_deposited.saturating_sub(principal - recovered);
+    }
+    smart_vault.total_allocated = still_external;
+    smart_vault.active = false;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    msg!("Emergency withdrawal recovered {} from strategies", recovered);
+    Ok(())
+}
+
+pub fn close_strategy_adapter(ctx: Context<CloseStrategyAdapter>) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    let strategy_adapter = &ctx.accounts.strategy_adapter;
+    let strategy_id = strategy_adapter.strategy_id;
+
+    require!(
+        !smart_vault.strategies.iter().any(|allocation| allocation.strategy_id == strategy_id),
+        ErrorCode::StrategyStillAllocated
+    );
+    require!(
+        strategy_adapter.deployed_amount == 0
+            && strategy_adapter.external_amount == 0
+            && ctx.accounts.position_token_account.amount == 0,
+        ErrorCode::StrategyNotEmpty
+    );
+
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.position_token_account.to_account_info(),
+            destination: ctx.accounts.curator.to_account_info(),
+            authority: smart_vault.to_account_info(),
+        },
+        signer,
+    ))?;
+
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.strategy_adapter_count = smart_vault.strategy_adapter_count.checked_sub(1).ok_or(ErrorCode::ArithmeticOverflow)?;
+
+    msg!("Strategy adapter {} closed", strategy_id);
+    Ok(())
+}
+
+// The share mint is left behind with no supply, since the token program cannot close
+// mints. With the vault closed, nothing can mint against it again.
+pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+    let smart_vault = &ctx.accounts.smart_vault;
+    require!(smart_vault.strategy_adapter_count == 0, ErrorCode::StrategyAdaptersOpen);
+
+    require!(
+        smart_vault.total_deposited == 0
+            && smart_vault.total_allocated == 0
+            && smart_vault.claimable_yield == 0
+            && smart_vault.queued_withdrawals == 0
+            && smart_vault.reserved_withdrawals == 0
+            && ctx.accounts.share_mint.supply == 0
+            && ctx.accounts.vault_token_account.amount == 0,
+        ErrorCode::VaultNotEmpty
+    );
+
+    let seeds = &[
+        b"smart-vault",
+        smart_vault.owner.as_ref(),
+        smart_vault.collateral_mint.as_ref(),
+        &[smart_vault.bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::close_account(CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        CloseAccount {
+            account: ctx.accounts.vault_token_account.to_account_info(),
+            destination: ctx.accounts.owner.to_account_info(),
+            authority: smart_vault.to_account_info(),
+        },
+        signer,
+    ))?;
+
+    // Closed by hand, as the history only exists for vaults that initialized it
+    let yield_history = ctx.accounts.yield_history.to_account_info();
+    if yield_history.owner == &crate::ID {
+        let owner = ctx.accounts.owner.to_account_info();
+        **owner.try_borrow_mut_lamports()? = owner.lamports().checked_add(yield_history.lamports()).ok_or(ErrorCode::ArithmeticOverflow)?;
+        **yield_history.try_borrow_mut_lamports()? = 0;
+        yield_history.assign(&system_program::ID);
+        yield_history.realloc(0, false)?;
+    }
+
+    msg!("Smart vault closed");
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Only the vault guardian or owner can perform this action")]
+    Unauthorized,
+    #[msg("Vault still holds funds, shares or pending withdrawals")]
+    VaultNotEmpty,
+    #[msg("Close the vault's strategy adapters before closing the vault")]
+    StrategyAdaptersOpen,
+    #[msg("Strategy is still in the vault's allocation")]
+    StrategyStillAllocated,
+    #[msg("Strategy still holds funds")]
+    StrategyNotEmpty,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,319 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount};
+
+use crate::global_config::GlobalConfig;
+use crate::smart_vaults::{
+    load_strategy_positions, transfer_from_vault, unwind_strategies, SmartVault,
+};
+
+// A claim on vault funds that could not be paid out of idle liquidity when requested.
+// The shares are burned up front, so the amount owed is fixed at the request price.
+#[account]
+pub struct WithdrawalTicket {
+    pub smart_vault: Pubkey,
+    pub withdrawer: Pubkey,
+    pub ticket_id: u64,
+    pub amount: u64,        // Underlying owed to the withdrawer
+    pub requested_at: i64,
+    pub fulfilled: bool,    // Set once the amount is sitting idle in the vault, reserved for this ticket
+    pub bump: u8,
+}
+
+impl WithdrawalTicket {
+    pub const SPACE: usize = 8 + 32 + 32 + 8 + 8 + 8 + 1 + 1;
+}
+
+// The ticket is only created when idle liquidity cannot pay the withdrawal straight away
+#[derive(Accounts)]
+pub struct RequestWithdrawal<'info> {
+    #[account(mut)]
+    pub withdrawer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        constraint = withdrawer_share_account.owner == withdrawer.key(),
+        constraint = withdrawer_share_account.mint == smart_vault.share_mint
+    )]
+    pub withdrawer_share_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = smart_vault.share_mint
+    )]
+    pub share_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        constraint = withdrawer_token_account.owner == withdrawer.key(),
+        constraint = withdrawer_token_account.mint == smart_vault.collateral_mint
+    )]
+    pub withdrawer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = withdrawer,
+        space = WithdrawalTicket::SPACE,
+        seeds = [
+            b"withdrawal-ticket",
+            smart_vault.key().as_ref(),
+            smart_vault.next_withdrawal_ticket_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub withdrawal_ticket: Option<Account<'info, WithdrawalTicket>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+// Permissionless: keepers unwind strategies to fill queued tickets.
+// Strategy adapters are passed as remaining accounts, as for `rebalance_strategies`.
+#[derive(Accounts)]
+pub struct FulfillWithdrawal<'info> {
+    pub keeper: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        has_one = smart_vault,
+        constraint = !withdrawal_ticket.fulfilled @ ErrorCode::TicketAlreadyFulfilled
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Strategy adapters are only needed when claiming an unfulfilled ticket after the maximum delay
+#[derive(Accounts)]
+pub struct ClaimWithdrawal<'info> {
+    #[account(mut)]
+    pub withdrawer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"smart-vault", smart_vault.owner.as_ref(), smart_vault.collateral_mint.as_ref()],
+        bump = smart_vault.bump
+    )]
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        mut,
+        has_one = smart_vault,
+        has_one = withdrawer,
+        close = withdrawer
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    #[account(
+        mut,
+        constraint = withdrawer_token_account.owner == withdrawer.key(),
+        constraint = withdrawer_token_account.mint == smart_vault.collateral_mint
+    )]
+    pub withdrawer_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = vault_token_account.key() == smart_vault.vault_token_account
+    )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+
+    #[account(
+        seeds = [b"global-config"],
+        bump = global_config.bump
+    )]
+    pub global_config: Account<'info, GlobalConfig>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+// Implementation of functions
+
+pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, amount: u64) -> Result<()> {
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    require!(amount > 0, ErrorCode::InvalidWithdrawalAmount);
+    require!(amount <= smart_vault.total_deposited, ErrorCode::InvalidWithdrawalAmount);
+
+    let shares = smart_vault.shares_for_withdrawal(amount, ctx.accounts.share_mint.supply)?;
+    require!(shares <= ctx.accounts.withdrawer_share_account.amount, ErrorCode::InsufficientShares);
+
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.share_mint.to_account_info(),
+                from: ctx.accounts.withdrawer_share_account.to_account_info(),
+                authority: ctx.accounts.withdrawer.to_account_info(),
+            },
+        ),
+        shares,
+    )?;
+
+    let current_time = Clock::get()?.unix_timestamp;
+
+    // Paid out directly when free idle liquidity covers it, refunding an unneeded ticket
+    if smart_vault.free_idle_balance(&ctx.accounts.vault_token_account) >= amount {
+        transfer_from_vault(
+            smart_vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.withdrawer_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        let smart_vault = &mut ctx.accounts.smart_vault;
+        smart_vault.withdraw_deposits(amount)?;
+        smart_vault.last_update_time = current_time;
+
+        if let Some(withdrawal_ticket) = &ctx.accounts.withdrawal_ticket {
+            withdrawal_ticket.close(ctx.accounts.withdrawer.to_account_info())?;
+        }
+
+        msg!("Withdrawal of {} settled from idle liquidity", amount);
+        return Ok(());
+    }
+
+    let withdrawal_ticket = ctx.accounts.withdrawal_ticket.as_mut().ok_or(ErrorCode::MissingWithdrawalTicket)?;
+    withdrawal_ticket.smart_vault = smart_vault.key();
+    withdrawal_ticket.withdrawer = ctx.accounts.withdrawer.key();
+    withdrawal_ticket.ticket_id = smart_vault.next_withdrawal_ticket_id;
+    withdrawal_ticket.amount = amount;
+    withdrawal_ticket.requested_at = current_time;
+    withdrawal_ticket.fulfilled = false;
+    withdrawal_ticket.bump = ctx.bumps.withdrawal_ticket.ok_or(ErrorCode::MissingWithdrawalTicket)?;
+
+    // The amount leaves the vault's assets now but stays deployed until a keeper unwinds it
+    smart_vault.queue_withdrawal(amount)?;
+    smart_vault.next_withdrawal_ticket_id += 1;
+    smart_vault.last_update_time = current_time;
+
+    msg!("Withdrawal ticket {} queued for {}", withdrawal_ticket.ticket_id, amount);
+    Ok(())
+}
+
+pub fn fulfill_withdrawal<'info>(ctx: Context<'_, '_, 'info, 'info, FulfillWithdrawal<'info>>) -> Result<()> {
+    let amount = ctx.accounts.withdrawal_ticket.amount;
+
+    fill_ticket(
+        &mut ctx.accounts.smart_vault,
+        &ctx.accounts.vault_token_account,
+        &ctx.accounts.token_program,
+        ctx.remaining_accounts,
+        amount,
+    )?;
+    ctx.accounts.withdrawal_ticket.fulfilled = true;
+
+    Ok(())
+}
+
+pub fn claim_withdrawal<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimWithdrawal<'info>>) -> Result<()> {
+    let withdrawal_ticket = &ctx.accounts.withdrawal_ticket;
+    let amount = withdrawal_ticket.amount;
+
+    // Past the maximum delay the withdrawer can unwind strategies themselves
+    if !withdrawal_ticket.fulfilled {
+        let current_time = Clock::get()?.unix_timestamp;
+        let claimable_at = withdrawal_ticket
+            .requested_at
+            .checked_add(ctx.accounts.global_config.max_withdrawal_delay)
+            .ok_or(ErrorCode::ArithmeticOverflow)?;
+        require!(current_time >= claimable_at, ErrorCode::TicketNotFulfilled);
+
+        fill_ticket(
+            &mut ctx.accounts.smart_vault,
+            &ctx.accounts.vault_token_account,
+            &ctx.accounts.token_program,
+            ctx.remaining_accounts,
+            amount,
+        )?;
+    }
+
+    transfer_from_vault(
+        &ctx.accounts.smart_vault,
+        &ctx.accounts.vault_token_account,
+        &ctx.accounts.withdrawer_token_account,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    let smart_vault = &mut ctx.accounts.smart_vault;
+    smart_vault.release_withdrawal(amount)?;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+// Helper functions
+
+// Moves a ticket's amount from the queue into the reserved idle balance, unwinding
+// strategies for whatever free idle liquidity cannot cover. Illiquid principal is kept
+// within the deposits left after queued withdrawals, so unwinding covers the queue
+// barring strategy losses.
+fn fill_ticket<'info>(
+    smart_vault: &mut Account<'info, SmartVault>,
+    vault_token_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    remaining_accounts: &'info [AccountInfo<'info>],
+    amount: u64,
+) -> Result<()> {
+    let idle_balance = smart_vault.free_idle_balance(vault_token_account);
+
+    if idle_balance < amount {
+        let mut positions = load_strategy_positions(smart_vault, remaining_accounts)?;
+        let unwound = unwind_strategies(
+            smart_vault,
+            &mut positions,
+            vault_token_account,
+            token_program,
+            amount - idle_balance,
+        )?;
+        require!(idle_balance + unwound >= amount, ErrorCode::InsufficientLiquidity);
+    }
+
+    smart_vault.reserve_withdrawal(amount)?;
+    smart_vault.last_update_time = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Withdrawal amount must be positive and within the vault's deposits")]
+    InvalidWithdrawalAmount,
+    #[msg("Not enough vault shares for this withdrawal")]
+    InsufficientShares,
+    #[msg("Withdrawal ticket has already been fulfilled")]
+    TicketAlreadyFulfilled,
+    #[msg("Withdrawal ticket is not fulfilled and the maximum delay has not passed")]
+    TicketNotFulfilled,
+    #[msg("Strategies could not be unwound enough to fill the ticket")]
+    InsufficientLiquidity,
+    #[msg("Idle liquidity cannot cover the withdrawal, a withdrawal ticket is required")]
+    MissingWithdrawalTicket,
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
@@ -0,0 +1,313 @@
+use anchor_lang::prelude::*;
+
+use crate::global_config::MAX_BPS;
+use crate::smart_vaults::{SmartVault, PRICE_PER_SHARE_SCALE};
+
+// Enough slots to cover the 30 day window at the minimum snapshot interval
+pub const YIELD_HISTORY_CAPACITY: usize = 64;
+// Retained snapshots are at least this far apart. More frequent harvests move the latest
+// snapshot forward instead of adding one.
+pub const MIN_SNAPSHOT_INTERVAL: i64 = 12 * 60 * 60;
+pub const APY_WINDOW_7D: i64 = 7 * 24 * 60 * 60;
+pub const APY_WINDOW_30D: i64 = 30 * 24 * 60 * 60;
+const SECONDS_PER_YEAR: i64 = 365 * 24 * 60 * 60;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct YieldSnapshot {
+    pub timestamp: i64,
+    pub price_per_share: u64,       // Scaled by PRICE_PER_SHARE_SCALE
+    pub distributed_per_share: u64, // Cumulative yield paid out per share, same scale
+}
+
+impl YieldSnapshot {
+    pub const SIZE: usize = 8 + 8 + 8;
+
+    // Value a share accrued since inception, counting yield paid out to the owner
+    fn growth_index(&self) -> u64 {
+        self.price_per_share.saturating_add(self.distributed_per_share)
+    }
+}
+
+// Fixed-size ring buffer of price-per-share snapshots, written at each harvest
+#[account]
+pub struct YieldHistory {
+    pub smart_vault: Pubkey,
+    pub head: u8, // Index of the latest snapshot
+    pub distributed_per_share: u64,
+    pub snapshots: Vec<YieldSnapshot>, // Grows up to YIELD_HISTORY_CAPACITY, then wraps
+    pub bump: u8,
+}
+
+impl YieldHistory {
+    pub const SPACE: usize = 8 + 32 + 1 + 8 + 4 + YIELD_HISTORY_CAPACITY * YieldSnapshot::SIZE + 1;
+
+    pub fn latest(&self) -> Option<&YieldSnapshot> {
+        self.snapshots.get(self.head as usize)
+    }
+
+    // Records the vault's price per share, plus any yield paid out instead of compounded
+    pub fn record(&mut self, timestamp: i64, price_per_share: u64, distributed: u64, share_supply: u64) -> Result<()> {
+        if distributed > 0 && share_supply > 0 {
+            let per_share = (distributed as u128)
+                .checked_mul(PRICE_PER_SHARE_SCALE as u128)
+                .ok_or(ErrorCode::ArithmeticOverflow)?
+                / share_supply as u128;
+            self.distributed_per_share = u64::try_from(per_share)
+                .ok()
+                .and_then(|per_share| self.distributed_per_share.checked_add(per_share))
+                .ok_or(ErrorCode::ArithmeticOverflow)?;
+        }
+
+        let snapshot = YieldSnapshot {
+            timestamp,
+            price_per_share,
+            distributed_per_share: self.distributed_per_share,
+        };
+
+        // The latest snapshot stays open until it is a full interval past the one before it.
+        // Comparing against the latest itself would let harvests more frequent than the
+        // interval slide a single snapshot forward forever.
+        let head = self.head as usize;
+        let len = self.snapshots.len();
+        let latest_is_open = len > 1
+            && self.snapshots[head].timestamp - self.snapshots[(head + len - 1) % len].timestamp < MIN_SNAPSHOT_INTERVAL;
+
+        if len == 0 {
+            self.snapshots.push(snapshot);
+            self.head = 0;
+        } else if latest_is_open {
+            self.snapshots[head] = snapshot;
+        } else {
+            let next = (head + 1) % YIELD_HISTORY_CAPACITY;
+            if next == len {
+                self.snapshots.push(snapshot);
+            } else {
+                self.snapshots[next] = snapshot;
+            }
+            self.head = next as u8;
+        }
+
+        Ok(())
+    }
+
+    // Simple annualized return in basis points over the trailing window ending at the latest
+    // snapshot. Uses the newest snapshot at least `window` old, or the oldest one available
+    // while the history is shorter than the window.
+    pub fn trailing_apy_bps(&self, window: i64) -> Result<u64> {
+        let Some(latest) = self.latest() else {
+            return Ok(0);
+        };
+
+        let start = self
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.timestamp <= latest.timestamp - window)
+            .max_by_key(|snapshot| snapshot.timestamp)
+            .or_else(|| self.snapshots.iter().min_by_key(|snapshot| snapshot.timestamp))
+            .unwrap_or(latest);
+
+        let elapsed = latest.timestamp - start.timestamp;
+        if elapsed <= 0 || start.price_per_share == 0 {
+            return Ok(0);
+        }
+
+        let gain = latest.growth_index().saturating_sub(start.growth_index()) as u128;
+        let apy = gain
+            .checked_mul(MAX_BPS as u128 * SECONDS_PER_YEAR as u128)
+            .ok_or(ErrorCode::ArithmeticOverflow)?
+            / (start.price_per_share as u128 * elapsed as u128);
+
+        u64::try_from(apy).map_err(|_| ErrorCode::ArithmeticOverflow.into())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct VaultApyReport {
+    pub apy_7d_bps: u64,
+    pub apy_30d_bps: u64,
+    pub price_per_share: u64,
+    pub last_snapshot_time: i64,
+}
+
+#[derive(Accounts)]
+pub struct InitializeYieldHistory<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = YieldHistory::SPACE,
+        seeds = [b"yield-history", smart_vault.key().as_ref()],
+        bump
+    )]
+    pub yield_history: Box<Account<'info, YieldHistory>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetVaultApy<'info> {
+    pub smart_vault: Account<'info, SmartVault>,
+
+    #[account(
+        seeds = [b"yield-history", smart_vault.key().as_ref()],
+        bump = yield_history.bump,
+        has_one = smart_vault
+    )]
+    pub yield_history: Box<Account<'info, YieldHistory>>,
+}
+
+// Implementation of functions
+
+// Permissionless, so histories can be added for vaults created before they existed
+pub fn initialize_yield_history(ctx: Context<InitializeYieldHistory>) -> Result<()> {
+    let yield_history = &mut ctx.accounts.yield_history;
+
+    yield_history.smart_vault = ctx.accounts.smart_vault.key();
+    yield_history.head = 0;
+    yield_history.distributed_per_share = 0;
+    yield_history.snapshots = Vec::new();
+    yield_history.bump = ctx.bumps.yield_history;
+
+    Ok(())
+}
+
+pub fn get_vault_apy(ctx: Context<GetVaultApy>) -> Result<VaultApyReport> {
+    let yield_history = &ctx.accounts.yield_history;
+    let latest = yield_history.latest().copied().unwrap_or_default();
+
+    Ok(VaultApyReport {
+        apy_7d_bps: yield_history.trailing_apy_bps(APY_WINDOW_7D)?,
+        apy_30d_bps: yield_history.trailing_apy_bps(APY_WINDOW_30D)?,
+        price_per_share: latest.price_per_share,
+        last_snapshot_time: latest.timestamp,
+    })
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Arithmetic overflow")]
+    ArithmeticOverflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    const DAY: i64 = 24 * HOUR;
+
+    fn yield_history() -> YieldHistory {
+        YieldHistory {
+            smart_vault: Pubkey::new_unique(),
+            head: 0,
+            distributed_per_share: 0,
+            snapshots: Vec::new(),
+            bump: 255,
+        }
+    }
+
+    // Price per share gaining 0.1% of its starting value a day
+    fn price_on(day: i64) -> u64 {
+        PRICE_PER_SHARE_SCALE + day as u64 * 1_000_000
+    }
+
+    fn record_days(yield_history: &mut YieldHistory, days: std::ops::Range<i64>) {
+        for day in days {
+            yield_history.record(day * DAY, price_on(day), 0, 1_000_000).unwrap();
+        }
+    }
+
+    #[test]
+    fn apy_is_zero_without_elapsed_time() {
+        let mut yield_history = yield_history();
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 0);
+
+        record_days(&mut yield_history, 0..1);
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 0);
+    }
+
+    #[test]
+    fn apy_uses_the_oldest_snapshot_while_the_history_is_short() {
+        let mut yield_history = yield_history();
+        record_days(&mut yield_history, 0..6);
+
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 3_650);
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_30D).unwrap(), 3_650);
+    }
+
+    #[test]
+    fn snapshots_within_the_minimum_interval_overwrite_the_latest() {
+        let mut yield_history = yield_history();
+        yield_history.record(0, price_on(0), 0, 1_000_000).unwrap();
+        yield_history.record(HOUR, price_on(1), 0, 1_000_000).unwrap();
+        assert_eq!(yield_history.snapshots.len(), 2);
+
+        yield_history.record(MIN_SNAPSHOT_INTERVAL - 1, price_on(2), 0, 1_000_000).unwrap();
+        assert_eq!(yield_history.snapshots.len(), 2);
+        assert_eq!(yield_history.head, 1);
+        assert_eq!(yield_history.latest().unwrap().timestamp, MIN_SNAPSHOT_INTERVAL - 1);
+        assert_eq!(yield_history.latest().unwrap().price_per_share, price_on(2));
+
+        // Open until a full interval past the first snapshot
+        yield_history.record(MIN_SNAPSHOT_INTERVAL, price_on(3), 0, 1_000_000).unwrap();
+        assert_eq!(yield_history.snapshots.len(), 2);
+        yield_history.record(MIN_SNAPSHOT_INTERVAL + HOUR, price_on(4), 0, 1_000_000).unwrap();
+        assert_eq!(yield_history.snapshots.len(), 3);
+        assert_eq!(yield_history.head, 2);
+    }
+
+    #[test]
+    fn frequent_harvests_keep_snapshots_a_full_interval_apart() {
+        let mut yield_history = yield_history();
+
+        // Every three hours for ten days, gaining 0.3% a day
+        for hour in (0..240).step_by(3) {
+            yield_history.record(hour * HOUR, PRICE_PER_SHARE_SCALE + hour as u64 * 125_000, 0, 1_000_000).unwrap();
+        }
+
+        assert_eq!(yield_history.latest().unwrap().timestamp, 237 * HOUR);
+        let mut retained: Vec<i64> = yield_history.snapshots.iter().map(|snapshot| snapshot.timestamp).collect();
+        retained.sort();
+        assert_eq!(retained.len(), 21);
+        assert!(retained[..20].windows(2).all(|pair| pair[1] - pair[0] >= MIN_SNAPSHOT_INTERVAL));
+
+        // Measured from hour 60, the newest snapshot a week before the latest
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 10_868);
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_30D).unwrap(), 10_950);
+    }
+
+    #[test]
+    fn ring_buffer_wraps_over_the_oldest_snapshots() {
+        let mut yield_history = yield_history();
+        record_days(&mut yield_history, 0..100);
+
+        assert_eq!(yield_history.snapshots.len(), YIELD_HISTORY_CAPACITY);
+        assert_eq!(yield_history.head as usize, 99 % YIELD_HISTORY_CAPACITY);
+        assert_eq!(yield_history.latest().unwrap().timestamp, 99 * DAY);
+        let oldest = yield_history.snapshots.iter().map(|snapshot| snapshot.timestamp).min().unwrap();
+        assert_eq!(oldest, 36 * DAY);
+
+        // Measured from days 92 and 69, or from the oldest snapshot left for longer windows
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 3_342);
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_30D).unwrap(), 3_414);
+        assert_eq!(yield_history.trailing_apy_bps(100 * DAY).unwrap(), 3_523);
+    }
+
+    #[test]
+    fn apy_counts_yield_paid_out_per_share() {
+        let mut yield_history = yield_history();
+
+        // The same 0.1% a day, paid out instead of compounded
+        for day in 0..6 {
+            let distributed = if day == 0 { 0 } else { 1_000 };
+            yield_history.record(day * DAY, PRICE_PER_SHARE_SCALE, distributed, 1_000_000).unwrap();
+        }
+
+        assert_eq!(yield_history.latest().unwrap().distributed_per_share, 5_000_000);
+        assert_eq!(yield_history.trailing_apy_bps(APY_WINDOW_7D).unwrap(), 3_650);
+    }
+}
@@ -0,0 +1,265 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import { StablefundsProgram } from "../target/types/stablefunds_program";
+import { MockJitoVault } from "../target/types/mock_jito_vault";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  getAccount,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { expect } from "chai";
+
+// Exercises the Jito restaking strategy adapter against the local stand-in vault program
+describe("jito_restaking", () => {
+  anchor.setProvider(anchor.AnchorProvider.env());
+
+  const program = anchor.workspace.StablefundsProgram as Program<StablefundsProgram>;
+  const jito = anchor.workspace.MockJitoVault as Program<MockJitoVault>;
+  const provider = anchor.getProvider() as anchor.AnchorProvider;
+  const wallet = provider.wallet as anchor.Wallet;
+
+  const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+  const EPOCH_LENGTH = 8; // Slots per cooldown epoch in the stand-in
+  const STRATEGY_ID = 0;
+  const DEPOSIT = 1_000_000_000;
+  const REWARD = 50_000_000;
+
+  const pda = (seeds: Buffer[], programId = program.programId) =>
+    PublicKey.findProgramAddressSync(seeds, programId)[0];
+
+  const waitForSlots = async (slots: number) => {
+    const target = (await provider.connection.getSlot()) + slots;
+    while ((await provider.connection.getSlot()) < target) {
+      await new Promise((resolve) => setTimeout(resolve, 400));
+    }
+  };
+
+  let supportedMint: PublicKey;
+  let smartVault: PublicKey;
+  let strategyAdapter: PublicKey;
+  let positionTokenAccount: PublicKey;
+  let jitoConfig: PublicKey;
+  let jitoVault: PublicKey;
+  let vrtMint: PublicKey;
+  let jitoVaultTokenAccount: PublicKey;
+  let jitoFeeTokenAccount: PublicKey;
+  let jitoPosition: PublicKey;
+  let staker: PublicKey;
+  let ticketBase: PublicKey;
+  let withdrawalTicket: PublicKey;
+
+  before(async () => {
+    const globalConfig = pda([Buffer.from("global-config")]);
+    const params = {
+      maxManagementFeeBps: 200,
+      maxPerformanceFeeBps: 2000,
+      maxWithdrawalDelay: new BN(0),
+      maxVaultTvl: new BN("18446744073709551615"),
+      minDepositFloor: new BN(1),
+      jitoVaultProgram: jito.programId,
+    };
+    if (await provider.connection.getAccountInfo(globalConfig)) {
+      await program.methods.updateGlobalConfig(params).accountsPartial({ authority: wallet.publicKey }).rpc();
+    } else {
+      const programData = pda([program.programId.toBuffer()], BPF_LOADER_UPGRADEABLE_ID);
+      await program.methods
+        .initializeGlobalConfig(params)
+        .accountsPartial({ authority: wallet.publicKey, program: program.programId, programData })
+        .rpc();
+    }
+
+    supportedMint = await createMint(provider.connection, wallet.payer, wallet.publicKey, null, 9);
+
+    // Stand-in Jito vault for the supported mint
+    jitoConfig = pda([Buffer.from("config")], jito.programId);
+    await jito.methods.initializeConfig(new BN(EPOCH_LENGTH)).accountsPartial({ admin: wallet.publicKey }).rpc();
+    jitoVault = pda([Buffer.from("vault"), supportedMint.toBuffer()], jito.programId);
+    vrtMint = pda([Buffer.from("vrt-mint"), jitoVault.toBuffer()], jito.programId);
+    jitoVaultTokenAccount = getAssociatedTokenAddressSync(supportedMint, jitoVault, true);
+    await jito.methods
+      .initializeVault()
+      .accountsPartial({ vault: jitoVault, supportedMint, admin: wallet.publicKey })
+      .rpc();
+    jitoFeeTokenAccount = await createAccount(provider.connection, wallet.payer, vrtMint, wallet.publicKey, Keypair.generate());
+
+    // Auto-compounding smart vault allocating everything to the restaking strategy
+    const vaultTokenAccount = Keypair.generate();
+    smartVault = pda([Buffer.from("smart-vault"), wallet.publicKey.toBuffer(), supportedMint.toBuffer()]);
+    await program.methods
+      .initializeSmartVault({ name: "Restaked SOL", riskLevel: 3, autoCompound: true })
+      .accountsPartial({
+        owner: wallet.publicKey,
+        collateralMint: supportedMint,
+        smartVault,
+        vaultTokenAccount: vaultTokenAccount.publicKey,
+      })
+      .signers([vaultTokenAccount])
+      .rpc();
+
+    strategyAdapter = pda([Buffer.from("strategy-adapter"), smartVault.toBuffer(), Buffer.from([STRATEGY_ID])]);
+    positionTokenAccount = pda([Buffer.from("strategy-position"), smartVault.toBuffer(), Buffer.from([STRATEGY_ID])]);
+    await program.methods
+      .registerStrategyAdapter(STRATEGY_ID, 3)
+      .accountsPartial({ curator: wallet.publicKey, smartVault, collateralMint: supportedMint, assetMint: supportedMint })
+      .rpc();
+    await program.methods
+      .updateStrategyAllocation([{ strategyId: STRATEGY_ID, allocationBps: 10_000 }])
+      .accountsPartial({ curator: wallet.publicKey, smartVault })
+      .remainingAccounts([{ pubkey: strategyAdapter, isWritable: false, isSigner: false }])
+      .rpc();
+
+    const depositorTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      wallet.payer,
+      supportedMint,
+      wallet.publicKey
+    );
+    await mintTo(provider.connection, wallet.payer, supportedMint, depositorTokenAccount.address, wallet.payer, DEPOSIT);
+    await program.methods
+      .depositToStrategy(new BN(DEPOSIT))
+      .accountsPartial({
+        depositor: wallet.publicKey,
+        smartVault,
+        depositorTokenAccount: depositorTokenAccount.address,
+        vaultTokenAccount: vaultTokenAccount.publicKey,
+      })
+      .rpc();
+    await program.methods
+      .rebalanceStrategies()
+      .accountsPartial({ curator: wallet.publicKey, smartVault, vaultTokenAccount: vaultTokenAccount.publicKey })
+      .remainingAccounts([
+        { pubkey: strategyAdapter, isWritable: true, isSigner: false },
+        { pubkey: positionTokenAccount, isWritable: true, isSigner: false },
+      ])
+      .rpc();
+
+    jitoPosition = pda([Buffer.from("jito-position"), strategyAdapter.toBuffer()]);
+    staker = pda([Buffer.from("jito-staker"), strategyAdapter.toBuffer()]);
+    ticketBase = pda([Buffer.from("jito-ticket-base"), jitoPosition.toBuffer()]);
+    withdrawalTicket = pda(
+      [Buffer.from("vault_staker_withdrawal_ticket"), jitoVault.toBuffer(), ticketBase.toBuffer()],
+      jito.programId
+    );
+    await program.methods
+      .initializeJitoPosition()
+      .accountsPartial({
+        curator: wallet.publicKey,
+        smartVault,
+        strategyAdapter,
+        jitoVaultProgram: jito.programId,
+        jitoVault,
+        assetMint: supportedMint,
+        vrtMint,
+      })
+      .rpc();
+  });
+
+  it("Restakes deployed funds into the Jito vault", async () => {
+    const position = await program.account.jitoPosition.fetch(jitoPosition);
+
+    await program.methods
+      .jitoRestake(new BN(DEPOSIT), new BN(DEPOSIT))
+      .accountsPartial({
+        curator: wallet.publicKey,
+        smartVault,
+        strategyAdapter,
+        positionTokenAccount,
+        jitoPosition,
+        staker,
+        stakerTokenAccount: position.stakerTokenAccount,
+        stakerVrtTokenAccount: position.stakerVrtTokenAccount,
+        jitoVaultProgram: jito.programId,
+        jitoConfig,
+        jitoVault,
+        vrtMint,
+        jitoVaultTokenAccount,
+        jitoVaultFeeTokenAccount: jitoFeeTokenAccount,
+      })
+      .rpc();
+
+    const vrt = await getAccount(provider.connection, position.stakerVrtTokenAccount);
+    expect(vrt.amount.toString()).to.equal(DEPOSIT.toString());
+
+    const adapter = await program.account.strategyAdapter.fetch(strategyAdapter);
+    expect(adapter.externalAmount.toString()).to.equal(DEPOSIT.toString());
+    expect(adapter.deployedAmount.toString()).to.equal(DEPOSIT.toString());
+  });
+
+  it("Returns principal and rewards after the withdrawal cooldown", async () => {
+    // Rewards land in the Jito vault and raise the VRT exchange rate
+    await mintTo(provider.connection, wallet.payer, supportedMint, jitoVaultTokenAccount, wallet.payer, REWARD);
+    await jito.methods.updateVaultBalance().accountsPartial({ vault: jitoVault }).rpc();
+
+    const position = await program.account.jitoPosition.fetch(jitoPosition);
+    await program.methods
+      .jitoRequestUnstake(new BN(DEPOSIT))
+      .accountsPartial({
+        curator: wallet.publicKey,
+        smartVault,
+        strategyAdapter,
+        jitoPosition,
+        staker,
+        stakerVrtTokenAccount: position.stakerVrtTokenAccount,
+        ticketBase,
+        jitoVaultProgram: jito.programId,
+        jitoConfig,
+        jitoVault,
+        vrtMint,
+        withdrawalTicket,
+      })
+      .rpc();
+
+    const burnTicket = () =>
+      program.methods
+        .jitoBurnTicket()
+        .accountsPartial({
+          keeper: wallet.publicKey,
+          smartVault,
+          strategyAdapter,
+          positionTokenAccount,
+          jitoPosition,
+          staker,
+          stakerTokenAccount: position.stakerTokenAccount,
+          jitoVaultProgram: jito.programId,
+          jitoConfig,
+          jitoVault,
+          jitoVaultTokenAccount,
+          vrtMint,
+          withdrawalTicket,
+          withdrawalTicketTokenAccount: getAssociatedTokenAddressSync(vrtMint, withdrawalTicket, true),
+          jitoVaultFeeTokenAccount: jitoFeeTokenAccount,
+          jitoProgramFeeTokenAccount: jitoFeeTokenAccount,
+          systemProgram: SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc();
+
+    // Still cooling down
+    let failed = false;
+    try {
+      await burnTicket();
+    } catch (err) {
+      failed = true;
+      expect(JSON.stringify(err.logs)).to.contain("WithdrawalTicketNotWithdrawable");
+    }
+    expect(failed).to.equal(true);
+
+    await waitForSlots(EPOCH_LENGTH * 2);
+    await burnTicket();
+
+    const positionAccount = await getAccount(provider.connection, positionTokenAccount);
+    expect(positionAccount.amount.toString()).to.equal((DEPOSIT + REWARD).toString());
+
+    const adapter = await program.account.strategyAdapter.fetch(strategyAdapter);
+    expect(adapter.externalAmount.toNumber()).to.equal(0);
+
+    const updated = await program.account.jitoPosition.fetch(jitoPosition);
+    expect(updated.pendingTicket).to.equal(null);
+    expect(updated.stakedAmount.toNumber()).to.equal(0);
+  });
+});